@@ -0,0 +1,89 @@
+//! Audio outputs and inputs for `dmenu_drun audio`, through pactl, which
+//! works with both PulseAudio and PipeWire.
+use std::process::{Command, Stdio};
+
+use crate::config::CONFIG;
+use crate::Result;
+
+#[derive(Clone, Debug)]
+pub struct Device {
+    /// `sink` or `source`
+    pub kind: &'static str,
+    pub name: String,
+    pub description: String,
+    pub default: bool,
+}
+
+fn pactl(args: &[&str]) -> Result<String> {
+    let output = Command::new("pactl")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| crate::tr!("Could not run pactl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The outputs, then the inputs. Monitors of outputs aren't listed.
+pub fn list() -> Result<Vec<Device>> {
+    let mut devices = Vec::new();
+    for kind in ["sink", "source"] {
+        let default = pactl(&[&format!("get-default-{}", kind)])?;
+        let mut name = None;
+        // `pactl list sinks` prints a block per sink, with `Name: ...` and
+        // `Description: ...` lines in it
+        for line in pactl(&["list", &format!("{}s", kind)])?.lines() {
+            let line = line.trim();
+            if let Some(x) = line.strip_prefix("Name: ") {
+                name = Some(x.to_string());
+            } else if let Some(description) = line.strip_prefix("Description: ") {
+                let Some(name) = name.take().filter(|x| !x.ends_with(".monitor")) else {
+                    continue;
+                };
+                devices.push(Device {
+                    kind,
+                    default: name == default.trim(),
+                    name,
+                    description: description.to_string(),
+                });
+            }
+        }
+    }
+    Ok(devices)
+}
+
+impl Device {
+    /// How the device is shown in the menu, `●` when it's the default.
+    pub fn display(&self) -> String {
+        let status = if self.default { "●" } else { "○" };
+        let kind = match self.kind {
+            "sink" => crate::tr!("output"),
+            _ => crate::tr!("input"),
+        };
+        format!("{} {} ({})", status, self.description, kind)
+    }
+
+    /// Makes the device the default. With `move_streams = true` in the
+    /// `[audio]` section, what's playing (or recording) moves to it too.
+    pub fn command(&self) -> Command {
+        let set = format!("pactl set-default-{} \"$1\"", self.kind);
+        let script = if CONFIG.get("audio", "move_streams") == Some("true") {
+            // `sink-inputs` play to sinks, `source-outputs` record from sources
+            let streams = match self.kind {
+                "sink" => "sink-input",
+                _ => "source-output",
+            };
+            format!(
+                "{} && pactl list short {}s | cut -f1 | while read -r id; do pactl move-{} \"$id\" \"$1\"; done",
+                set, streams, streams
+            )
+        } else {
+            set
+        };
+        let mut command = Command::new("sh");
+        command.args(["-c", &script, "sh", &self.name]);
+        command
+    }
+}
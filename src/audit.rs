@@ -0,0 +1,44 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::Command;
+
+use crate::config::CONFIG;
+
+/// Appends a launch to the file set with `audit_log` in the `[general]`
+/// section, as a tab separated line:
+/// ```text
+/// 1650000000      drun    ff      firefox.desktop gtk-launch firefox.desktop
+/// ```
+/// That's the time, the mode, what was typed or selected, the entry it
+/// resolved to (empty for typed commands) and the command line. Every line
+/// is written with a single append, so concurrent launches don't interleave.
+pub fn record(mode: &str, query: &str, entry: Option<&str>, command: &Command) {
+    let Some(path) = CONFIG.get("general", "audit_log") else {
+        return;
+    };
+    let env = crate::launch::explicit_env(command).into_iter();
+    let argv = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|x| x.to_string_lossy().to_string());
+    let fields = [
+        crate::history::now().to_string(),
+        mode.to_string(),
+        query.to_string(),
+        entry.unwrap_or_default().to_string(),
+        crate::launch::shell_join(&env.chain(argv).collect::<Vec<_>>()),
+    ];
+    // Tabs and newlines would break up the record
+    let line = fields.map(|x| x.replace(['\t', '\n'], " ")).join("\t") + "\n";
+    let path = std::path::PathBuf::from(crate::expand_tilde(path));
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+    if let Err(e) = written {
+        eprintln!("dmenu_drun: could not write the audit log: {}", e);
+    }
+}
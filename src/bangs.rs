@@ -0,0 +1,38 @@
+//! Search bangs like `!w dmenu`, opening the query with a search engine.
+use crate::config::CONFIG;
+
+/// Bangs that work without configuring any, `{}` is the query.
+const DEFAULTS: [(&str, &str); 6] = [
+    ("g", "https://www.google.com/search?q={}"),
+    ("ddg", "https://duckduckgo.com/?q={}"),
+    (
+        "w",
+        "https://en.wikipedia.org/wiki/Special:Search?search={}",
+    ),
+    ("yt", "https://www.youtube.com/results?search_query={}"),
+    ("gh", "https://github.com/search?q={}"),
+    ("crates", "https://crates.io/search?q={}"),
+];
+
+/// The URL to open for `input` if it starts with a bang, from the
+/// `[bangs]` section (e.g. `aw = https://wiki.archlinux.org/?search={}`)
+/// or the defaults.
+pub fn url(input: &str) -> Option<String> {
+    let (bang, query) = input.strip_prefix('!')?.split_once(' ')?;
+    let template = CONFIG
+        .get("bangs", bang)
+        .or_else(|| DEFAULTS.iter().find(|x| x.0 == bang).map(|x| x.1))?;
+    Some(template.replace("{}", &percent_encode(query.trim())))
+}
+
+/// Escapes everything but unreserved characters, as in RFC 3986.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|x| match x {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (x as char).to_string()
+            }
+            x => format!("%{:02X}", x),
+        })
+        .collect()
+}
@@ -0,0 +1,92 @@
+//! Bluetooth devices for `dmenu_drun bluetooth`, through BlueZ with
+//! bluetoothctl.
+use std::process::{Command, Stdio};
+
+use crate::config::CONFIG;
+use crate::Result;
+
+#[derive(Clone, Debug)]
+pub struct Device {
+    pub address: String,
+    pub name: String,
+    pub paired: bool,
+    pub connected: bool,
+}
+
+/// Runs bluetoothctl, returning (address, name) of the `Device` lines it
+/// printed.
+fn bluetoothctl(args: &[&str]) -> Result<Vec<(String, String)>> {
+    let output = Command::new("bluetoothctl")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| crate::tr!("Could not run bluetoothctl: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|x| x.strip_prefix("Device "))
+        .filter_map(|x| x.split_once(' '))
+        .map(|(address, name)| (address.to_string(), name.to_string()))
+        .collect())
+}
+
+/// The devices BlueZ knows about: connected ones first, then paired, then
+/// those nearby. With `scan` in the `[bluetooth]` section (e.g. `5s`), it
+/// first looks for devices nearby for that long.
+pub fn list() -> Result<Vec<Device>> {
+    if let Some(scan) = CONFIG
+        .get("bluetooth", "scan")
+        .and_then(crate::history::parse_duration)
+    {
+        bluetoothctl(&["--timeout", &scan.to_string(), "scan", "on"])?;
+    }
+    let paired = bluetoothctl(&["devices", "Paired"])?;
+    let connected = bluetoothctl(&["devices", "Connected"])?;
+    let mut devices = bluetoothctl(&["devices"])?
+        .into_iter()
+        .map(|(address, name)| Device {
+            paired: paired.iter().any(|x| x.0 == address),
+            connected: connected.iter().any(|x| x.0 == address),
+            address,
+            name,
+        })
+        .collect::<Vec<_>>();
+    devices.sort_by_key(|x| (!x.connected, !x.paired));
+    Ok(devices)
+}
+
+impl Device {
+    /// How the device is shown in the menu: `●` when connected, `○` when
+    /// paired, `◌` when it's only nearby.
+    pub fn display(&self) -> String {
+        let status = match (self.connected, self.paired) {
+            (true, _) => "●",
+            (false, true) => "○",
+            (false, false) => "◌",
+        };
+        format!("{} {} ({})", status, self.name, self.address)
+    }
+
+    /// Connects, or disconnects if the device is connected already. Devices
+    /// that aren't paired are paired and trusted first.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new("bluetoothctl");
+        match (self.connected, self.paired) {
+            (true, _) => {
+                command.args(["disconnect", &self.address]);
+            }
+            (false, true) => {
+                command.args(["connect", &self.address]);
+            }
+            (false, false) => {
+                command = Command::new("sh");
+                command.args([
+                    "-c",
+                    "bluetoothctl pair \"$1\" && bluetoothctl trust \"$1\" && bluetoothctl connect \"$1\"",
+                    "sh",
+                    &self.address,
+                ]);
+            }
+        }
+        command
+    }
+}
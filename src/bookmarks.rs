@@ -0,0 +1,210 @@
+//! Browser bookmarks for `dmenu_drun bookmarks`, from Firefox's
+//! `places.sqlite` (read with sqlite3) and Chromium's `Bookmarks` file.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::{glob_match, CONFIG};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Firefox,
+    Chromium,
+}
+
+/// Name, kind, where the profiles are relative to the home directory (the
+/// config directory for Chromium) and the command that opens a link.
+const BROWSERS: [(&str, Kind, &str, &str); 5] = [
+    ("firefox", Kind::Firefox, ".mozilla/firefox", "firefox"),
+    ("librewolf", Kind::Firefox, ".librewolf", "librewolf"),
+    ("chromium", Kind::Chromium, "chromium", "chromium"),
+    (
+        "google-chrome",
+        Kind::Chromium,
+        "google-chrome",
+        "google-chrome",
+    ),
+    (
+        "brave",
+        Kind::Chromium,
+        "BraveSoftware/Brave-Browser",
+        "brave",
+    ),
+];
+
+#[derive(Clone, Debug)]
+pub struct Bookmark {
+    pub title: String,
+    pub url: String,
+    /// `<browser>/<profile directory>`, e.g. `chromium/Default`
+    pub profile: String,
+    browser: usize,
+    dir: PathBuf,
+}
+
+/// The bookmarks of every browser profile that matches a pattern in
+/// `profiles` in the `[bookmarks]` section (e.g. `firefox/*.default-release
+/// chromium/Default`), or of all of them.
+pub fn all() -> Vec<Bookmark> {
+    let patterns = CONFIG
+        .get("bookmarks", "profiles")
+        .map(|x| x.split_whitespace().collect::<Vec<_>>());
+    let mut bookmarks = Vec::new();
+    for (i, (name, kind, root, _)) in BROWSERS.iter().enumerate() {
+        let root = match kind {
+            Kind::Firefox => dirs::home_dir(),
+            Kind::Chromium => dirs::config_dir(),
+        }
+        .unwrap_or_default()
+        .join(root);
+        let Ok(dirs) = std::fs::read_dir(root) else {
+            continue;
+        };
+        for dir in dirs.flatten().map(|x| x.path()) {
+            let profile = format!(
+                "{}/{}",
+                name,
+                dir.file_name().unwrap_or_default().to_string_lossy()
+            );
+            if patterns
+                .as_ref()
+                .is_some_and(|x| !x.iter().any(|x| glob_match(x, &profile)))
+            {
+                continue;
+            }
+            let found = match kind {
+                Kind::Firefox => firefox(&dir.join("places.sqlite")),
+                Kind::Chromium => chromium(&dir.join("Bookmarks")),
+            };
+            bookmarks.extend(found.into_iter().map(|(title, url)| Bookmark {
+                title,
+                url,
+                profile: profile.clone(),
+                browser: i,
+                dir: dir.clone(),
+            }));
+        }
+    }
+    bookmarks
+}
+
+impl Bookmark {
+    /// Opens the bookmark in the profile it's from. The browser's command can
+    /// be changed in the `[bookmarks]` section, e.g. `chromium =
+    /// chromium-browser`.
+    pub fn open_command(&self) -> Command {
+        let (name, kind, _, default) = BROWSERS[self.browser];
+        let program = CONFIG.get("bookmarks", name).unwrap_or(default);
+        let mut command = Command::new(program);
+        match kind {
+            Kind::Firefox => command.arg("--profile").arg(&self.dir),
+            Kind::Chromium => command.arg(format!(
+                "--profile-directory={}",
+                self.dir.file_name().unwrap_or_default().to_string_lossy()
+            )),
+        };
+        command.arg(&self.url);
+        command
+    }
+}
+
+/// (title, url) of the bookmarks in Firefox's `places.sqlite`. The database
+/// is opened as immutable, as Firefox keeps it locked while it runs.
+fn firefox(places: &Path) -> Vec<(String, String)> {
+    if !places.exists() {
+        return Vec::new();
+    }
+    let query = "SELECT b.title, p.url FROM moz_bookmarks b JOIN moz_places p ON b.fk = p.id \
+                 WHERE b.type = 1 AND p.url NOT LIKE 'place:%'";
+    let output = Command::new("sqlite3")
+        .args(["-readonly", "-separator", "\t"])
+        .arg(format!("file:{}?immutable=1", places.display()))
+        .arg(query)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("dmenu_drun: could not run sqlite3: {}", e);
+            return Vec::new();
+        }
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|x| x.rsplit_once('\t'))
+        .map(|(title, url)| (title.to_string(), url.to_string()))
+        .collect()
+}
+
+/// (title, url) of the bookmarks in Chromium's `Bookmarks`, a JSON tree of
+/// folders with `"type": "url"` objects in them.
+fn chromium(file: &Path) -> Vec<(String, String)> {
+    let Ok(json) = std::fs::read_to_string(file) else {
+        return Vec::new();
+    };
+    let mut bookmarks = Vec::new();
+    // The objects we're in, with the last key seen in each
+    let mut objects: Vec<(HashMap<String, String>, Option<String>)> = Vec::new();
+    let mut chars = json.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => objects.push((HashMap::new(), None)),
+            '}' => {
+                let Some((object, _)) = objects.pop() else {
+                    continue;
+                };
+                if let (Some("url"), Some(name), Some(url)) = (
+                    object.get("type").map(String::as_str),
+                    object.get("name"),
+                    object.get("url"),
+                ) {
+                    bookmarks.push((name.clone(), url.clone()));
+                }
+            }
+            '"' => {
+                let string = json_string(&mut chars);
+                while chars.peek().is_some_and(|x| x.is_whitespace()) {
+                    chars.next();
+                }
+                let Some((object, key)) = objects.last_mut() else {
+                    continue;
+                };
+                if chars.peek() == Some(&':') {
+                    *key = Some(string);
+                } else if let Some(key) = key.take() {
+                    object.insert(key, string);
+                }
+            }
+            _ => {}
+        }
+    }
+    bookmarks
+}
+
+/// Reads the rest of a JSON string, after the opening quote.
+fn json_string(chars: &mut impl Iterator<Item = char>) -> String {
+    let mut string = String::new();
+    // `\u` escapes, which can be UTF-16 surrogate pairs
+    let mut units = Vec::new();
+    while let Some(c) = chars.next() {
+        let escaped = match c {
+            '\\' => chars.next(),
+            _ => None,
+        };
+        if escaped == Some('u') {
+            let hex = chars.by_ref().take(4).collect::<String>();
+            units.extend(u16::from_str_radix(&hex, 16).ok());
+            continue;
+        }
+        string.extend(char::decode_utf16(units.drain(..)).map(|x| x.unwrap_or('\u{fffd}')));
+        match (c, escaped) {
+            ('\\', Some('n')) => string.push('\n'),
+            ('\\', Some('t')) => string.push('\t'),
+            ('\\', Some(x)) => string.push(x),
+            ('\\', None) | ('"', _) => break,
+            (c, _) => string.push(c),
+        }
+    }
+    string
+}
@@ -0,0 +1,23 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::Result;
+
+/// Copies `text` to the clipboard with wl-copy on Wayland, xclip on X11.
+pub fn copy(text: &str) -> Result {
+    let mut command = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        Command::new("wl-copy")
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard"]);
+        command
+    };
+    let mut child = command.stdin(Stdio::piped()).spawn()?;
+    let written = child
+        .stdin
+        .take()
+        .expect("Could not write to clipboard")
+        .write_all(text.as_bytes());
+    child.wait()?;
+    Ok(written?)
+}
@@ -0,0 +1,708 @@
+//! The subcommands, one function each, called from `run` in main.rs.
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use itertools::Itertools;
+
+use crate::config::{self, CONFIG};
+use crate::desktop::{self, DesktopEntry};
+use crate::menu::Menu as _;
+use crate::{
+    apply_filters, audio, bluetooth, bookmarks, browse_categories, cache_stats, clipboard, display,
+    drives, expand_tilde, files, history, launch, launch_selection, load_cache, menu, menu_names,
+    net, open_with, option, overrides, pass, places, preselect, rebuild_cache, remote, resolve,
+    resolve_with_args, script, search, selection_commands, set_default_app, show_menu,
+    spawn_audited, speech, stats, stdin_entries, strip_root_prefix, tr, Cache, Flags, Result,
+};
+
+/// What the subcommands showing a menu share: the entries, the history and
+/// how to show and launch them.
+pub struct Session {
+    pub args: Vec<String>,
+    pub flags: Flags,
+    pub cache: Cache,
+    pub history: history::History,
+    pub scripts: script::Scripts,
+    pub backend: menu::Picker,
+    pub spawner: Box<dyn launch::Spawner>,
+    /// The active filters, with the amount of entries they removed
+    pub filtered: Vec<(&'static str, usize)>,
+    /// The active filters joined by `+`, for `{mode}` in the prompt and the
+    /// audit log
+    pub mode: String,
+    pub prompt: Option<String>,
+    pub sort: Option<history::Sort>,
+    /// Terminates printed lines
+    pub end: char,
+}
+
+impl Session {
+    /// Loads the cache and history, and applies the filters.
+    pub fn load(args: Vec<String>, flags: Flags) -> Result<Self> {
+        let sort = option(&args, "--sort")
+            .or_else(|| CONFIG.get("menu", "sort"))
+            .and_then(|x| {
+                let sort = history::Sort::parse(x);
+                if sort.is_none() {
+                    eprintln!("dmenu_drun: unknown sort `{}`, ignoring", x);
+                }
+                sort
+            });
+        let null = args.contains(&"-0".to_string()) || args.contains(&"--null".to_string());
+
+        let backend = menu::Picker {
+            backend: menu::Backend::from_config(),
+            auto_select: flags.auto_select,
+            timeout: flags.timeout,
+        };
+        let mut spawner: Box<dyn launch::Spawner> = if args.contains(&"--dry-run".to_string()) {
+            Box::new(launch::DryRun)
+        } else {
+            Box::new(launch::Detached)
+        };
+        if launch::is_sandboxed() {
+            spawner = Box::new(launch::Portal(spawner));
+        }
+
+        let mut cache = load_cache(&flags)?;
+        if args.contains(&"--verbose".to_string()) {
+            for (path, reason) in &cache.warnings {
+                eprintln!("dmenu_drun: {}: {}", path, reason);
+            }
+        }
+        let history = history::History::load(flags.history_file.as_deref());
+        cache.aliases.extend(history.aliases.clone());
+        let scripts = script::Scripts::load();
+        cache.commands.extend(scripts.entries());
+        // Only on request, a stdin that's never closed would block forever
+        if args.contains(&"--stdin".to_string()) {
+            cache
+                .commands
+                .extend(stdin_entries(if null { b'\0' } else { b'\n' }));
+        }
+
+        let filtered = apply_filters(&mut cache, &args, &flags);
+        let mode = filtered
+            .iter()
+            .map(|x| x.0)
+            .filter(|x| *x != "hide" && *x != "exec")
+            .join("+");
+        let mode = if mode.is_empty() {
+            "drun".to_string()
+        } else {
+            mode
+        };
+        let prompt = CONFIG
+            .get("menu", "prompt")
+            .map(|x| x.replace("{mode}", &mode));
+
+        Ok(Self {
+            args,
+            flags,
+            cache,
+            history,
+            scripts,
+            backend,
+            spawner,
+            filtered,
+            mode,
+            prompt,
+            sort,
+            end: if null { '\0' } else { '\n' },
+        })
+    }
+
+    /// Shows the menu of the entries, see `show_menu`.
+    fn show_menu(&self) -> Result<String> {
+        show_menu(
+            &self.cache,
+            &self.history,
+            &self.scripts,
+            &self.backend,
+            self.prompt.as_deref(),
+            self.sort,
+        )
+    }
+
+    /// Launches what was selected in the menu, see `launch_selection`.
+    fn launch(&mut self, output: &str) -> Result {
+        launch_selection(
+            &self.cache,
+            &mut self.history,
+            &self.scripts,
+            &self.backend,
+            &mut *self.spawner,
+            &self.flags,
+            &self.mode,
+            output,
+        )
+    }
+}
+
+/// Shows the menu and launches the selection, without a subcommand.
+pub fn menu(s: &mut Session) -> Result {
+    let mut selected = option(&s.args, "--select").and_then(|x| preselect(&mut s.cache, x));
+    // Shows the menu again after every selection, until nothing is selected
+    let looping = s.args.contains(&"--loop".to_string());
+    let mut done = false;
+
+    loop {
+        let output = match selected.take() {
+            Some(x) => x,
+            None => s.show_menu()?,
+        };
+        // `timeout_default` in `[menu]` is launched when `--timeout` closed the
+        // menu, only the first time when looping
+        let output = match CONFIG.get("menu", "timeout_default") {
+            Some(default) if output.is_empty() && !done && menu::timed_out() => default.to_string(),
+            _ => output,
+        };
+        if output.is_empty() {
+            if done {
+                std::process::exit(0);
+            }
+            // Nothing was selected
+            speech::say(&tr!("Nothing selected"));
+            std::process::exit(1);
+        }
+        let result = if s.args.contains(&"--stdout".to_string()) {
+            print!("{}{}", output, s.end);
+            // Whoever reads it shouldn't wait for the loop to end
+            std::io::stdout().flush().map_err(Into::into)
+        } else {
+            s.launch(&output)
+        };
+        if !looping {
+            return result;
+        }
+        match result {
+            Ok(()) => done = true,
+            Err(e) => eprintln!("dmenu_drun: {}", e),
+        }
+    }
+}
+
+/// `history clear` forgets launched entries and learned abbreviations,
+/// `history merge <file>` adds the history of another machine to this one.
+pub fn history(flags: &Flags) -> Result {
+    match flags.operands.first() {
+        Some(x) if x == "clear" => history::History::clear(flags.history_file.as_deref()),
+        Some(x) if x == "merge" => {
+            let file = flags
+                .operands
+                .get(1)
+                .ok_or_else(|| format!("{}: dmenu_drun history merge <file>", tr!("Usage")))?;
+            let other = std::fs::read_to_string(expand_tilde(file))
+                .map_err(|e| tr!("Could not read {}: {}", file, e))?;
+            let mut history = history::History::load(flags.history_file.as_deref());
+            history.merge(history::History::parse(&other));
+            history.save()
+        }
+        _ => Err(format!("{}: dmenu_drun history clear|merge <file>", tr!("Usage")).into()),
+    }
+}
+
+/// Checks desktop files for mistakes, all of them by default.
+pub fn lint(flags: &Flags) -> Result {
+    let files = flags.operands.iter().map(PathBuf::from).collect_vec();
+    crate::lint(files)
+}
+
+/// Rebuilds the cache if it's outdated, so the next menu opens quickly.
+pub fn warm(flags: &Flags) -> Result {
+    load_cache(flags)?;
+    Ok(())
+}
+
+/// Browses desktop files by the XDG menu's categories.
+pub fn categories(s: &mut Session) -> Result {
+    browse_categories(
+        &s.cache,
+        &mut s.history,
+        &s.scripts,
+        &s.backend,
+        &mut *s.spawner,
+        &s.flags,
+    )
+}
+
+/// Opens the selected desktop file in `$EDITOR`.
+pub fn edit(s: &mut Session) -> Result {
+    s.cache.retain(|_, v| v.ends_with(".desktop"));
+    let output = s.show_menu()?;
+    if let Some(entry) = resolve(&s.cache, &output) {
+        overrides::edit(entry)?;
+    }
+    Ok(())
+}
+
+/// Makes a common change to a local copy of a desktop file.
+pub fn tweak(s: &mut Session) -> Result {
+    let file_name = match s.flags.operands.first() {
+        Some(id) if id.ends_with(".desktop") => id.clone(),
+        Some(id) => format!("{}.desktop", id),
+        None => {
+            s.cache.retain(|_, v| v.ends_with(".desktop"));
+            let output = s.show_menu()?;
+            match resolve(&s.cache, &output) {
+                Some(entry) => entry.clone(),
+                None => std::process::exit(1),
+            }
+        }
+    };
+    match overrides::tweak(&file_name, &s.backend)? {
+        Some(path) => {
+            rebuild_cache(&s.flags)?;
+            println!("{}", path.display());
+            Ok(())
+        }
+        None => std::process::exit(1),
+    }
+}
+
+/// Deletes the local copy of a desktop file shadowing another.
+pub fn restore(s: &mut Session) -> Result {
+    let file_name = match s.flags.operands.first() {
+        Some(id) if id.ends_with(".desktop") => id.clone(),
+        Some(id) => format!("{}.desktop", id),
+        None => {
+            // Only the entries with a local copy
+            let shadowing = overrides::shadowing();
+            s.cache.retain(|_, v| shadowing.contains(v));
+            s.cache.commands.clear();
+            let output = s.show_menu()?;
+            match resolve(&s.cache, &output) {
+                Some(entry) => entry.clone(),
+                None => std::process::exit(1),
+            }
+        }
+    };
+    if !overrides::restore(&file_name, &s.backend)? {
+        std::process::exit(1);
+    }
+    rebuild_cache(&s.flags)
+}
+
+/// Creates a desktop file for an executable.
+pub fn create(s: &mut Session) -> Result {
+    let binary = match s.flags.operands.first() {
+        Some(binary) => binary.clone(),
+        None => {
+            s.cache.retain(|_, v| !v.ends_with(".desktop"));
+            s.cache.commands.clear();
+            let output = s.show_menu()?;
+            match resolve(&s.cache, &output) {
+                Some(entry) => entry.clone(),
+                None if output.is_empty() => std::process::exit(1),
+                None => output,
+            }
+        }
+    };
+    match overrides::create(&binary, &s.backend)? {
+        // The cache is rebuilt next time, as the directory changed
+        Some(path) => {
+            println!("{}", path.display());
+            Ok(())
+        }
+        None => std::process::exit(1),
+    }
+}
+
+/// Adds the selection to the hidden entries.
+pub fn hide(s: &mut Session) -> Result {
+    let output = s.show_menu()?;
+    // Desktop file names and executables are less likely to change
+    let word = resolve(&s.cache, &output)
+        .map(String::as_str)
+        .unwrap_or(&output);
+    if word.is_empty() || word.contains(char::is_whitespace) {
+        std::process::exit(1);
+    }
+    let section = CONFIG.profile_section("filter");
+    // The profile's list replaces the base one, so it starts out as a copy
+    if !CONFIG.section(&section).iter().any(|(k, _)| k == "hide") {
+        for x in CONFIG
+            .get("filter", "hide")
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            config::add_to_list(&section, "hide", x)?;
+        }
+    }
+    config::add_to_list(&section, "hide", word)
+}
+
+/// Opens the man page of the selected executable.
+pub fn man(s: &mut Session) -> Result {
+    s.cache.retain(|_, v| !v.ends_with(".desktop"));
+    s.cache.commands.clear();
+    let output = s.show_menu()?;
+    if let Some(entry) = resolve(&s.cache, &output) {
+        let mut command = launch::in_terminal(&["man".to_string(), entry.clone()])?;
+        spawn_audited(&mut *s.spawner, "man", &output, Some(entry), &mut command)?;
+    }
+    Ok(())
+}
+
+/// Opens the directory of the selected desktop file or executable.
+pub fn reveal(s: &mut Session) -> Result {
+    s.cache.commands.clear();
+    let output = s.show_menu()?;
+    let Some(entry) = resolve(&s.cache, &output) else {
+        std::process::exit(1);
+    };
+    let path = if entry.ends_with(".desktop") {
+        DesktopEntry::find(entry).map(|x| x.path)
+    } else {
+        launch::which(entry)
+    };
+    let dir = path
+        .as_deref()
+        .and_then(Path::parent)
+        .ok_or_else(|| tr!("Could not find {}", entry))?;
+    let mut command = places::open_command(dir)?;
+    launch::pass_activation_token(&mut command);
+    spawn_audited(
+        &mut *s.spawner,
+        "reveal",
+        &output,
+        Some(entry),
+        &mut command,
+    )
+}
+
+/// Copies the command line of the selection instead of launching it.
+pub fn copy(s: &mut Session) -> Result {
+    let output = s.show_menu()?;
+    if output.is_empty() {
+        return Ok(());
+    }
+    // What would be launched, in a terminal or as root included
+    let (output, root) = strip_root_prefix(&output);
+    let (entry, args) = match resolve(&s.cache, output) {
+        Some(entry) => (Some(entry), Vec::new()),
+        None => resolve_with_args(&s.cache, output),
+    };
+    let (mut command, _) = selection_commands(&s.cache, output, entry, args, root)?;
+    launch::apply_environment(&mut command);
+    clipboard::copy(&launch::command_line(&command))
+}
+
+/// Opens a recent directory (`jump`), or a terminal in a bookmarked, XDG
+/// user or recent directory (`terminal`).
+pub fn jump(s: &mut Session, subcommand: &str) -> Result {
+    let dirs = if subcommand == "terminal" {
+        places::bookmarks()
+            .into_iter()
+            .chain(places::user_dirs())
+            .chain(places::recent())
+            .unique()
+            .collect_vec()
+    } else {
+        places::recent()
+    };
+    let lines = dirs.iter().map(|x| places::display(x)).collect_vec();
+    let output = s.backend.choose(&tr!("Directory"), &lines)?;
+    let Some(dir) = lines.iter().position(|x| *x == output).map(|i| &dirs[i]) else {
+        std::process::exit(1);
+    };
+    let mut command = if subcommand == "terminal" {
+        places::terminal_command(dir)?
+    } else {
+        places::open_command(dir)?
+    };
+    launch::pass_activation_token(&mut command);
+    spawn_audited(&mut *s.spawner, subcommand, &output, None, &mut command)
+}
+
+/// Launches an application on another machine over SSH.
+pub fn remote(s: &mut Session) -> Result {
+    let host = s.flags.operands.first();
+    let host = match host {
+        Some(host) => host.clone(),
+        None => match &remote::hosts()[..] {
+            [] => return Err(tr!("No hosts in the [remote] section").into()),
+            [host] => host.clone(),
+            hosts => s.backend.choose(&tr!("Host"), hosts)?,
+        },
+    };
+    if host.is_empty() {
+        std::process::exit(1);
+    }
+    let apps = remote::apps(&host)?;
+    let names = apps.iter().map(|x| x.0.clone()).collect_vec();
+    let output = s.backend.choose(&host, &names)?;
+    let Some((_, exec)) = apps.iter().find(|x| x.0 == output) else {
+        std::process::exit(1);
+    };
+    let mut command = remote::command(&host, exec)
+        .ok_or_else(|| tr!("Could not read the command of {}", output))?;
+    spawn_audited(&mut *s.spawner, "remote", &output, None, &mut command)
+}
+
+/// Opens a file found with fd or locate, `--open-with` asks with what.
+pub fn files(s: &mut Session) -> Result {
+    let query = s.flags.operands.first();
+    let files = files::search(query.map(String::as_str))?;
+    let lines = files.iter().map(|x| places::display(x)).collect_vec();
+    let output = s.backend.choose(&tr!("File"), &lines)?;
+    let Some(file) = lines.iter().position(|x| *x == output).map(|i| &files[i]) else {
+        std::process::exit(1);
+    };
+    let mut command = if s.args.contains(&"--open-with".to_string()) {
+        open_with(&s.cache, &s.backend, file)?
+    } else {
+        places::xdg_open(file)
+    };
+    launch::pass_activation_token(&mut command);
+    spawn_audited(&mut *s.spawner, "files", &output, None, &mut command)
+}
+
+/// Opens a path or URL with the default application, like xdg-open.
+pub fn open(s: &mut Session) -> Result {
+    let Some(target) = s.flags.operands.first() else {
+        return Err(format!("{}: dmenu_drun open <path|url>", tr!("Usage")).into());
+    };
+    let mut command = crate::open(&s.cache, &s.backend, target)?;
+    launch::pass_activation_token(&mut command);
+    spawn_audited(&mut *s.spawner, "open", target, None, &mut command)
+}
+
+/// Opens a bookmark of Firefox or Chromium.
+pub fn bookmarks(s: &mut Session) -> Result {
+    let bookmarks = bookmarks::all();
+    let lines = bookmarks
+        .iter()
+        .map(|x| format!("{} — {} [{}]", x.title, x.url, x.profile))
+        .collect_vec();
+    let output = s.backend.choose(&tr!("Bookmark"), &lines)?;
+    let Some(bookmark) = lines
+        .iter()
+        .position(|x| *x == output)
+        .map(|i| &bookmarks[i])
+    else {
+        std::process::exit(1);
+    };
+    let mut command = bookmark.open_command();
+    launch::pass_activation_token(&mut command);
+    spawn_audited(&mut *s.spawner, "bookmarks", &output, None, &mut command)
+}
+
+/// Sets the default audio output or input.
+pub fn audio(s: &mut Session) -> Result {
+    let devices = audio::list()?;
+    let lines = devices.iter().map(audio::Device::display).collect_vec();
+    let output = s.backend.choose(&tr!("Audio"), &lines)?;
+    let Some(device) = lines.iter().position(|x| *x == output).map(|i| &devices[i]) else {
+        std::process::exit(1);
+    };
+    spawn_audited(
+        &mut *s.spawner,
+        "audio",
+        &output,
+        None,
+        &mut device.command(),
+    )
+}
+
+/// Connects to or disconnects from a Bluetooth device.
+pub fn bluetooth(s: &mut Session) -> Result {
+    let devices = bluetooth::list()?;
+    let lines = devices.iter().map(bluetooth::Device::display).collect_vec();
+    let output = s.backend.choose(&tr!("Device"), &lines)?;
+    let Some(device) = lines.iter().position(|x| *x == output).map(|i| &devices[i]) else {
+        std::process::exit(1);
+    };
+    spawn_audited(
+        &mut *s.spawner,
+        "bluetooth",
+        &output,
+        None,
+        &mut device.command(),
+    )
+}
+
+/// Switches to another layout of the screens.
+pub fn display(s: &mut Session) -> Result {
+    let layouts = display::layouts();
+    let lines = layouts.iter().map(|x| x.0.clone()).collect_vec();
+    let output = s.backend.choose(&tr!("Layout"), &lines)?;
+    let Some((_, argv)) = layouts.iter().find(|x| x.0 == output) else {
+        std::process::exit(1);
+    };
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    spawn_audited(&mut *s.spawner, "display", &output, None, &mut command)
+}
+
+/// Mounts, opens or unmounts a removable drive.
+pub fn drives(s: &mut Session) -> Result {
+    let drives = drives::list()?;
+    let lines = drives.iter().map(drives::Drive::display).collect_vec();
+    let output = s.backend.choose(&tr!("Drive"), &lines)?;
+    let Some(drive) = lines.iter().position(|x| *x == output).map(|i| &drives[i]) else {
+        std::process::exit(1);
+    };
+    let mut command = match &drive.mountpoint {
+        Some(mountpoint) => {
+            let actions = [tr!("Open"), tr!("Unmount")];
+            let action = s.backend.choose(&drive.path, &actions)?;
+            match actions.iter().position(|x| *x == action) {
+                Some(0) => places::xdg_open(Path::new(mountpoint)),
+                Some(_) => drives::udisksctl("unmount", drive),
+                None => std::process::exit(1),
+            }
+        }
+        None if s.spawner.is_dry_run() => drives::udisksctl("mount", drive),
+        // Mounted right away, to know where to open it
+        None => places::xdg_open(Path::new(&drives::mount(drive)?)),
+    };
+    launch::pass_activation_token(&mut command);
+    spawn_audited(&mut *s.spawner, "drives", &output, None, &mut command)
+}
+
+/// Connects to or disconnects from a network.
+pub fn net(s: &mut Session) -> Result {
+    let networks = net::list()?;
+    let lines = networks.iter().map(net::Network::display).collect_vec();
+    let output = s.backend.choose(&tr!("Network"), &lines)?;
+    let Some(network) = lines
+        .iter()
+        .position(|x| *x == output)
+        .map(|i| &networks[i])
+    else {
+        std::process::exit(1);
+    };
+    let password = if network.needs_password() {
+        match s
+            .backend
+            .choose(&tr!("Password for {}", network.name), &[])?
+        {
+            x if x.is_empty() => std::process::exit(1),
+            x => Some(x),
+        }
+    } else {
+        None
+    };
+    let password = password.map(|x| net::password_file(&x)).transpose()?;
+    let mut command = network.command(password.as_deref());
+    let result = spawn_audited(&mut *s.spawner, "net", &output, None, &mut command);
+    // Only removed by the command when it runs
+    if let Some(password) = password.filter(|_| s.spawner.is_dry_run() || result.is_err()) {
+        let _ = std::fs::remove_file(password);
+    }
+    result
+}
+
+/// Copies or types a password from pass.
+pub fn pass(s: &mut Session) -> Result {
+    let entries = pass::entries();
+    let output = s.backend.choose(&tr!("Password"), &entries)?;
+    if !entries.contains(&output) {
+        std::process::exit(1);
+    }
+    // Not audited, the log shouldn't say which passwords are used
+    s.spawner.spawn(&mut pass::command(&output))
+}
+
+/// Searches with the installed GNOME Shell search providers.
+pub fn search(s: &mut Session) -> Result {
+    let query = match s.flags.operands.first() {
+        Some(x) => x.to_string(),
+        None => s.backend.choose(&tr!("Search"), &[])?,
+    };
+    if query.trim().is_empty() {
+        std::process::exit(1);
+    }
+    let providers = search::providers();
+    let results = search::search(&providers, &query);
+    let lines = results
+        .iter()
+        .map(|x| {
+            let app = desktop::DesktopEntry::find(&providers[x.provider].desktop_id)
+                .and_then(|e| e.get_localized("Name").map(ToString::to_string))
+                .unwrap_or_else(|| providers[x.provider].desktop_id.clone());
+            match &x.description {
+                Some(description) => format!("{} — {} ({})", x.name, description, app),
+                None => format!("{} ({})", x.name, app),
+            }
+        })
+        .collect_vec();
+    let output = s.backend.choose(&tr!("Result"), &lines)?;
+    let Some(result) = lines.iter().position(|x| *x == output).map(|i| &results[i]) else {
+        std::process::exit(1);
+    };
+    let mut command = providers[result.provider].activate(&result.id, &query);
+    spawn_audited(&mut *s.spawner, "search", &output, None, &mut command)
+}
+
+/// Chooses the application that opens a type of file.
+pub fn default(s: &mut Session) -> Result {
+    let target = s.flags.operands.first();
+    set_default_app(&s.cache, &s.backend, target.map(String::as_str))
+}
+
+/// `cache stats` shows where entries come from and what was filtered.
+pub fn cache(s: &mut Session) -> Result {
+    match s.flags.operands.first() {
+        Some(x) if x == "stats" => cache_stats(&s.cache, &s.filtered, &s.flags),
+        _ => Err(format!("{}: dmenu_drun cache stats", tr!("Usage")).into()),
+    }
+}
+
+/// Checks the menu and gtk-launch, and shows skipped files.
+pub fn doctor(s: &mut Session) -> Result {
+    crate::doctor(&s.cache, s.backend.backend)
+}
+
+/// Shows the most used entries and how often things are launched.
+pub fn stats(s: &mut Session) -> Result {
+    stats::print(
+        &s.cache.entries,
+        &s.history,
+        s.args.contains(&"--json".to_string()),
+    );
+    Ok(())
+}
+
+/// Launches a desktop file id, executable or entry without a menu.
+pub fn launch(s: &mut Session) -> Result {
+    let Some(target) = s.flags.operands.first() else {
+        return Err(format!("{}: dmenu_drun launch <desktop-id|name>", tr!("Usage")).into());
+    };
+    // A desktop file id (with or without `.desktop`) or executable,
+    // even if it's hidden from the menu, else a name from the menu
+    let id = [format!("{}.desktop", target), target.clone()]
+        .into_iter()
+        .find(|x| {
+            (x.ends_with(".desktop") && DesktopEntry::find(x).is_some())
+                || (!x.ends_with(".desktop") && launch::which(x).is_some())
+        });
+    let name = match id {
+        Some(id) => {
+            let name = s
+                .cache
+                .entries
+                .iter()
+                .find(|x| *x.1 == id)
+                .map_or_else(|| target.clone(), |x| x.0.clone());
+            s.cache.entries.insert(name.clone(), id);
+            name
+        }
+        None if resolve(&s.cache, target).is_some() || s.cache.commands.contains_key(target) => {
+            target.clone()
+        }
+        None => return Err(tr!("Nothing called {} was found", target).into()),
+    };
+    s.mode = "launch".to_string();
+    s.launch(&name)
+}
+
+/// Prints the menu entries.
+pub fn list(s: &mut Session) -> Result {
+    for name in menu_names(&s.cache, &s.history, s.sort) {
+        print!("{}{}", name, s.end);
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+lazy_static::lazy_static! {
+    pub static ref CONFIG_PATH: PathBuf = dirs::config_dir()
+        .unwrap_or_default()
+        .join("dmenu_drun")
+        .join("config");
+    pub static ref CONFIG: Config = Config::load();
+    /// Appended to the names of state files, see `namespaced`
+    static ref NAMESPACE: String = namespace();
+}
+
+/// `--profile`, see `set_profile`.
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// Makes the sections of profile `name` take precedence, see `Config`. Has
+/// to be called before `CONFIG` is first read.
+pub fn set_profile(name: &str) {
+    let _ = PROFILE.set(name.to_string());
+}
+
+/// The user's configuration, an ini file in the same format as desktop
+/// files:
+/// ```ini
+/// [sandbox]
+/// firefox.desktop = firejail firefox
+/// ```
+/// Keys in per-entry sections are glob patterns, matched against both the
+/// name shown in the menu and the desktop file name / executable.
+///
+/// With `--profile <name>`, sections named `[<section>:<name>]` take
+/// precedence over `[<section>]`.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    sections: HashMap<String, Vec<(String, String)>>,
+    profile: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let mut config = std::fs::read_to_string(&*CONFIG_PATH)
+            .map(|x| Self::parse(&x))
+            .unwrap_or_default();
+        config.profile = PROFILE.get().cloned();
+        config
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let mut sections: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        let mut current = String::new();
+        for line in s.lines().map(str::trim) {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                current = name.trim().to_string();
+                continue;
+            }
+            if let Some((k, v)) = line.split_once('=') {
+                sections
+                    .entry(current.clone())
+                    .or_default()
+                    .push((k.trim().to_string(), v.trim().to_string()));
+            }
+        }
+        Self {
+            sections,
+            profile: None,
+        }
+    }
+
+    pub fn section(&self, name: &str) -> &[(String, String)] {
+        self.sections.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The name passed to `--profile`, if any.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// The profile's own `[<section>:<profile>]`, or `section` itself
+    /// without one.
+    pub fn profile_section(&self, section: &str) -> String {
+        match &self.profile {
+            Some(profile) => format!("{}:{}", section, profile),
+            None => section.to_string(),
+        }
+    }
+
+    /// The sections to search for `section`, the profile's first.
+    fn sections(&self, section: &str) -> Vec<&[(String, String)]> {
+        let profile = self
+            .profile
+            .as_ref()
+            .map(|x| self.section(&format!("{}:{}", section, x)));
+        profile
+            .into_iter()
+            .chain(std::iter::once(self.section(section)))
+            .collect()
+    }
+
+    /// Returns the value of `key` in `section`, the last one wins.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections(section).into_iter().find_map(|x| {
+            x.iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        })
+    }
+
+    /// Returns every key in `section` with its value, the profile's
+    /// winning over the base section's.
+    pub fn merged(&self, section: &str) -> Vec<(&str, &str)> {
+        let mut merged: Vec<(&str, &str)> = Vec::new();
+        for x in self.sections(section) {
+            for (k, v) in x.iter().rev() {
+                if !merged.iter().any(|(x, _)| x == k) {
+                    merged.push((k, v));
+                }
+            }
+        }
+        merged
+    }
+
+    /// Returns the value of the first pattern in `section` that matches
+    /// any of `names`.
+    pub fn lookup(&self, section: &str, names: &[&str]) -> Option<&str> {
+        self.sections(section).into_iter().find_map(|x| {
+            x.iter()
+                .find(|(pattern, _)| names.iter().any(|x| glob_match(pattern, x)))
+                .map(|(_, v)| v.as_str())
+        })
+    }
+}
+
+/// Adds `word` to the space separated list in `key` in `section` of the
+/// config file, creating the key and section if needed. The rest of the
+/// file is left as it is.
+pub fn add_to_list(section: &str, key: &str, word: &str) -> crate::Result {
+    let config = std::fs::read_to_string(&*CONFIG_PATH).unwrap_or_default();
+    let mut lines = config.lines().map(ToString::to_string).collect::<Vec<_>>();
+    let header = format!("[{}]", section);
+    let start = lines.iter().position(|x| x.trim() == header);
+    match start {
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|x| x.trim().starts_with('['))
+                .map_or(lines.len(), |x| start + 1 + x);
+            // The last one wins, so that's the one to change
+            let line = lines[start + 1..end]
+                .iter()
+                .rposition(|x| x.split_once('=').is_some_and(|(k, _)| k.trim() == key));
+            match line {
+                Some(i) => {
+                    let line = &mut lines[start + 1 + i];
+                    if !line
+                        .split_once('=')
+                        .unwrap()
+                        .1
+                        .split_whitespace()
+                        .any(|x| x == word)
+                    {
+                        line.push(' ');
+                        line.push_str(word);
+                    }
+                }
+                None => lines.insert(start + 1, format!("{} = {}", key, word)),
+            }
+        }
+        None => {
+            if lines.last().is_some_and(|x| !x.is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(header);
+            lines.push(format!("{} = {}", key, word));
+        }
+    }
+    if let Some(parent) = CONFIG_PATH.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::write(&*CONFIG_PATH, lines.join("\n") + "\n")?)
+}
+
+/// Matches `s` against a shell-style pattern supporting `*` and `?`.
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let s = s.chars().collect::<Vec<_>>();
+    let (mut p, mut i) = (0, 0);
+    // Position of the last `*` and the input position it was tried at.
+    let mut backtrack = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match backtrack {
+                Some((star, pos)) => {
+                    p = star + 1;
+                    i = pos + 1;
+                    backtrack = Some((star, pos + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&x| x == '*')
+}
+
+/// `$XDG_CONFIG_HOME` and `$XDG_CONFIG_DIRS`, most important first.
+pub fn config_dirs() -> Vec<PathBuf> {
+    let dirs = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    dirs::config_dir()
+        .into_iter()
+        .chain(dirs.split(':').map(PathBuf::from))
+        .collect()
+}
+
+/// `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`, most important first.
+pub fn data_dirs() -> Vec<PathBuf> {
+    let dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs::data_local_dir()
+        .into_iter()
+        .chain(dirs.split(':').map(PathBuf::from))
+        .collect()
+}
+
+/// What the cache and history are kept apart by, from the space separated
+/// `namespace` in `[general]`: `uid` (the default), `session`
+/// (`$XDG_SESSION_ID`) and `host`. `none` shares them between all of those.
+fn namespace() -> String {
+    let mut parts = Vec::new();
+    for part in CONFIG
+        .get("general", "namespace")
+        .unwrap_or("uid")
+        .split_whitespace()
+    {
+        match part {
+            "uid" => parts.push(unsafe { libc::getuid() }.to_string()),
+            "session" => parts.extend(std::env::var("XDG_SESSION_ID").ok()),
+            "host" => parts.extend(hostname()),
+            "none" => {}
+            x => eprintln!("dmenu_drun: unknown namespace `{}`, ignoring", x),
+        }
+    }
+    parts.join(".")
+}
+
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|x| *x == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).to_string())
+}
+
+/// `path` with the namespace appended, e.g. `history.1000`, so users
+/// sharing a home (or `$XDG_CACHE_HOME`) don't write over each other's
+/// state.
+pub fn namespaced(path: PathBuf) -> PathBuf {
+    if NAMESPACE.is_empty() {
+        return path;
+    }
+    let mut path = path.into_os_string();
+    path.push(format!(".{}", *NAMESPACE));
+    path.into()
+}
@@ -0,0 +1,112 @@
+//! Unit conversion for input like `=5km in mi`, worked out without any
+//! external program.
+use crate::config::CONFIG;
+
+/// Units and how many of the base unit of their kind (meter, kilogram,
+/// liter, second, byte, meter per second) one is.
+const UNITS: &[(&str, &str, f64)] = &[
+    ("length", "m", 1.),
+    ("length", "km", 1000.),
+    ("length", "cm", 0.01),
+    ("length", "mm", 0.001),
+    ("length", "mi", 1609.344),
+    ("length", "yd", 0.9144),
+    ("length", "ft", 0.3048),
+    ("length", "in", 0.0254),
+    ("length", "nmi", 1852.),
+    ("mass", "kg", 1.),
+    ("mass", "g", 0.001),
+    ("mass", "mg", 0.000001),
+    ("mass", "t", 1000.),
+    ("mass", "lb", 0.45359237),
+    ("mass", "oz", 0.028349523125),
+    ("mass", "st", 6.35029318),
+    ("volume", "l", 1.),
+    ("volume", "ml", 0.001),
+    ("volume", "cl", 0.01),
+    ("volume", "gal", 3.785411784),
+    ("volume", "qt", 0.946352946),
+    ("volume", "pt", 0.473176473),
+    ("volume", "cup", 0.2365882365),
+    ("volume", "floz", 0.0295735295625),
+    ("time", "s", 1.),
+    ("time", "min", 60.),
+    ("time", "h", 3600.),
+    ("time", "d", 86400.),
+    ("time", "wk", 604800.),
+    ("data", "b", 1.),
+    ("data", "kb", 1e3),
+    ("data", "mb", 1e6),
+    ("data", "gb", 1e9),
+    ("data", "tb", 1e12),
+    ("data", "kib", 1024.),
+    ("data", "mib", 1048576.),
+    ("data", "gib", 1073741824.),
+    ("data", "tib", 1099511627776.),
+    ("speed", "m/s", 1.),
+    ("speed", "kmh", 1. / 3.6),
+    ("speed", "km/h", 1. / 3.6),
+    ("speed", "mph", 0.44704),
+    ("speed", "kn", 1852. / 3600.),
+];
+
+/// Converts `expr`, like `5km in mi`, `72f to c` or `10 eur in usd`,
+/// returning the amount and the unit it's in. Currencies are converted
+/// with the rates in the `[currency]` section, e.g. `usd = 1.08` for the
+/// amount of dollars one unit of the base currency buys.
+pub fn convert(expr: &str) -> Option<(f64, String)> {
+    let expr = expr.trim().to_lowercase();
+    let (from, to) = expr
+        .split_once(" in ")
+        .or_else(|| expr.split_once(" to "))?;
+    let from = from.trim();
+    let split = from
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(from.len());
+    let (amount, from) = from.split_at(split);
+    let amount = amount.parse::<f64>().ok()?;
+    let (from, to) = (from.trim(), to.trim());
+    let value = match (temperature(from), temperature(to)) {
+        (Some((to_kelvin, _)), Some((_, from_kelvin))) => from_kelvin(to_kelvin(amount)),
+        (None, None) => amount * factor(from, to)?,
+        _ => return None,
+    };
+    Some((value, to.to_string()))
+}
+
+type Conversion = fn(f64) -> f64;
+
+/// Functions to and from kelvin.
+fn temperature(unit: &str) -> Option<(Conversion, Conversion)> {
+    match unit {
+        "c" | "°c" => Some((|x| x + 273.15, |x| x - 273.15)),
+        "f" | "°f" => Some((
+            |x| (x - 32.) * 5. / 9. + 273.15,
+            |x| (x - 273.15) * 9. / 5. + 32.,
+        )),
+        "k" => Some((|x| x, |x| x)),
+        _ => None,
+    }
+}
+
+/// What to multiply an amount in `from` with to get it in `to`.
+fn factor(from: &str, to: &str) -> Option<f64> {
+    let unit = |name: &str| UNITS.iter().find(|x| x.1 == name);
+    if let (Some(from), Some(to)) = (unit(from), unit(to)) {
+        return (from.0 == to.0).then(|| from.2 / to.2);
+    }
+    let rate = |name: &str| {
+        CONFIG
+            .section("currency")
+            .iter()
+            .find(|x| x.0.to_lowercase() == name)
+            .and_then(|x| x.1.parse::<f64>().ok())
+    };
+    Some(rate(to)? / rate(from)?)
+}
+
+/// Formats `value` with at most 6 decimals, without trailing zeros.
+pub fn format(value: f64) -> String {
+    let s = format!("{:.6}", value);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
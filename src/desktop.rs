@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::{fs::File, path::Path, path::PathBuf};
+
+use crate::DESKTOP_DIRS;
+
+lazy_static::lazy_static! {
+    /// Suffixes to try for localized keys, best match first.
+    pub static ref LOCALE_SUFFIXES: Vec<String> = locale_suffixes();
+}
+
+/// The `[Desktop Entry]` group of a .desktop file.
+/// See also: https://specifications.freedesktop.org/desktop-entry-spec/latest/
+#[derive(Clone, Debug, Default)]
+pub struct DesktopEntry {
+    pub path: PathBuf,
+    pub keys: HashMap<String, String>,
+}
+
+impl DesktopEntry {
+    /// Parses the `[Desktop Entry]` group, other groups (actions) are
+    /// skipped.
+    pub fn parse(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::from_reader(path, File::open(path)?))
+    }
+
+    /// See `parse`, `path` is only used for the `%k` field code.
+    pub fn from_reader(path: &Path, reader: impl Read) -> Self {
+        let reader = BufReader::new(reader);
+        let mut keys = HashMap::new();
+        let mut in_group = false;
+        // A line that isn't valid UTF-8 is skipped, not the rest of the file
+        let lines = reader
+            .split(b'\n')
+            .map_while(Result::ok)
+            .filter_map(|x| String::from_utf8(x).ok());
+        for line in lines {
+            let line = line.trim();
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_group = line == "[Desktop Entry]";
+                continue;
+            }
+            if let Some((k, v)) = line.split_once('=').filter(|_| in_group) {
+                keys.entry(k.trim().to_string())
+                    .or_insert_with(|| unescape(v.trim()));
+            }
+        }
+        Self {
+            path: path.to_path_buf(),
+            keys,
+        }
+    }
+
+    /// Looks up a desktop file by its filename, e.g. `firefox.desktop`.
+    /// Later directories in `DESKTOP_DIRS` take precedence, the same way
+    /// they do when building the cache.
+    pub fn find(file_name: &str) -> Option<Self> {
+        DESKTOP_DIRS
+            .iter()
+            .rev()
+            .map(|x| x.join(file_name))
+            .find(|x| x.is_file())
+            .and_then(|x| Self::parse(&x).ok())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.keys.get(key).map(String::as_str)
+    }
+
+    /// Returns the best translation of `key` for the user's locales, see
+    /// `LOCALE_SUFFIXES`.
+    pub fn get_localized(&self, key: &str) -> Option<&str> {
+        LOCALE_SUFFIXES
+            .iter()
+            .find_map(|x| self.get(&format!("{}[{}]", key, x)))
+            .or_else(|| self.get(key))
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get(key) == Some("true")
+    }
+
+    /// Splits the `Exec` key into an argument vector, expanding field codes.
+    /// File and URL field codes are dropped, see `open_argv`.
+    pub fn exec_argv(&self) -> Option<Vec<String>> {
+        self.argv(None)
+    }
+
+    /// Like `exec_argv`, but passes `target` (a file or URL) for the first
+    /// file or URL field code, or after the other arguments if there's none.
+    pub fn open_argv(&self, target: &str) -> Option<Vec<String>> {
+        self.argv(Some(target))
+    }
+
+    fn argv(&self, mut target: Option<&str>) -> Option<Vec<String>> {
+        let exec = self.get("Exec")?;
+        let mut argv = Vec::new();
+        for arg in split_exec(exec) {
+            match arg.as_str() {
+                "%f" | "%F" | "%u" | "%U" => argv.extend(target.take().map(ToString::to_string)),
+                "%d" | "%D" | "%n" | "%N" | "%v" | "%m" => {}
+                "%i" => {
+                    if let Some(icon) = self.get("Icon") {
+                        argv.push("--icon".to_string());
+                        argv.push(icon.to_string());
+                    }
+                }
+                _ => argv.push(self.expand_field_codes(&arg)),
+            }
+        }
+        if argv.is_empty() {
+            return None;
+        }
+        argv.extend(target.map(ToString::to_string));
+        Some(argv)
+    }
+
+    /// Expands `%c` and `%k` inside an argument, in one pass so that the
+    /// `%` of `%%c` isn't taken for a field code. Others are left alone.
+    fn expand_field_codes(&self, arg: &str) -> String {
+        let mut out = String::with_capacity(arg.len());
+        let mut chars = arg.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => out.push('%'),
+                Some('c') => out.push_str(self.get_localized("Name").unwrap_or_default()),
+                Some('k') => out.push_str(&self.path.to_string_lossy()),
+                Some(x) => {
+                    out.push('%');
+                    out.push(x);
+                }
+                None => out.push('%'),
+            }
+        }
+        out
+    }
+}
+
+/// Keys defined by the spec, others have to start with `X-`.
+const KNOWN_KEYS: [&str; 25] = [
+    "Type",
+    "Version",
+    "Name",
+    "GenericName",
+    "NoDisplay",
+    "Comment",
+    "Icon",
+    "Hidden",
+    "OnlyShowIn",
+    "NotShowIn",
+    "DBusActivatable",
+    "TryExec",
+    "Exec",
+    "Path",
+    "Terminal",
+    "Actions",
+    "MimeType",
+    "Categories",
+    "Implements",
+    "Keywords",
+    "StartupNotify",
+    "StartupWMClass",
+    "URL",
+    "PrefersNonDefaultGPU",
+    "SingleMainWindow",
+];
+
+/// Checks a desktop file against the spec, as far as it matters to how it's
+/// read here. Returns the problems with their line number, 0 when they're
+/// about the whole file.
+pub fn lint(path: &Path) -> std::io::Result<Vec<(usize, String)>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut problems = Vec::new();
+    let mut seen = Vec::new();
+    let mut group = None;
+    for (i, line) in text.lines().enumerate().map(|(i, x)| (i + 1, x.trim())) {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if group.is_none() && line != "[Desktop Entry]" {
+                problems.push((i, crate::tr!("the first group must be [Desktop Entry]")));
+            }
+            group = Some(line.to_string());
+            continue;
+        }
+        let Some((k, v)) = line.split_once('=') else {
+            problems.push((i, crate::tr!("not a key=value pair")));
+            continue;
+        };
+        if group.as_deref() != Some("[Desktop Entry]") {
+            continue;
+        }
+        let k = k.trim();
+        let base = k.split('[').next().unwrap_or_default();
+        if !KNOWN_KEYS.contains(&base) && !base.starts_with("X-") {
+            problems.push((i, crate::tr!("unknown key `{}`", base)));
+        }
+        if seen.contains(&k) {
+            problems.push((i, crate::tr!("duplicate key `{}`, the first is used", k)));
+        }
+        seen.push(k);
+        for escape in bad_escapes(v.trim(), base == "Exec") {
+            problems.push((i, crate::tr!("invalid escape `{}` in `{}`", escape, k)));
+        }
+    }
+
+    let entry = DesktopEntry::from_reader(path, text.as_bytes());
+    if group.is_none() {
+        problems.push((0, crate::tr!("no [Desktop Entry] group")));
+        return Ok(problems);
+    }
+    for key in ["Type", "Name"] {
+        if entry.get(key).is_none() {
+            problems.push((0, crate::tr!("missing `{}`", key)));
+        }
+    }
+    if entry.get("Type") == Some("Application")
+        && entry.get("Exec").is_none()
+        && !entry.get_bool("DBusActivatable")
+    {
+        problems.push((0, crate::tr!("missing `Exec`")));
+    }
+    if let Some(exec) = entry.get("Exec") {
+        if exec.chars().filter(|x| *x == '"').count() % 2 != 0 {
+            problems.push((0, crate::tr!("unterminated quote in `Exec`")));
+        }
+        for arg in split_exec(exec) {
+            let mut chars = arg.chars();
+            while let Some(c) = chars.next() {
+                if c != '%' {
+                    continue;
+                }
+                match chars.next() {
+                    Some(c) if "fFuUdDnNickvm%".contains(c) => {}
+                    Some(c) => {
+                        problems.push((0, crate::tr!("unknown field code `%{}` in `Exec`", c)))
+                    }
+                    None => problems.push((0, crate::tr!("lone `%` in `Exec`"))),
+                }
+            }
+        }
+    }
+    if let Some(try_exec) = entry.get("TryExec") {
+        let found = if try_exec.starts_with('/') {
+            std::fs::metadata(try_exec).is_ok_and(|x| x.permissions().mode() & 0o111 != 0)
+        } else {
+            crate::launch::which(try_exec).is_some()
+        };
+        if !found {
+            problems.push((0, crate::tr!("`TryExec` program {} not found", try_exec)));
+        }
+    }
+    Ok(problems)
+}
+
+/// Escapes in a raw value that the spec doesn't define. In `Exec`, the ones
+/// quoting arguments are allowed too.
+fn bad_escapes(value: &str, exec: bool) -> Vec<String> {
+    let mut bad = Vec::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        match chars.next() {
+            Some('s' | 'n' | 't' | 'r' | '\\' | ';') => {}
+            Some('"' | '`' | '$') if exec => {}
+            Some(c) => bad.push(format!("\\{}", c)),
+            None => bad.push("\\".to_string()),
+        }
+    }
+    bad
+}
+
+/// Builds the list of locales to look for, following gettext: the
+/// colon-separated `$LANGUAGE` takes priority over `$LC_ALL`,
+/// `$LC_MESSAGES` and `$LANG`, unless those select the C locale. Each
+/// locale is expanded into the variants the spec allows to match, e.g.
+/// `sr_YU@Latn` → `sr_YU@Latn`, `sr_YU`, `sr@Latn`, `sr`.
+fn locale_suffixes() -> Vec<String> {
+    let var = |x| std::env::var(x).ok().filter(|x: &String| !x.is_empty());
+    let Some(locale) = var("LC_ALL")
+        .or_else(|| var("LC_MESSAGES"))
+        .or_else(|| var("LANG"))
+    else {
+        return Vec::new();
+    };
+    if locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let mut suffixes = Vec::new();
+    let chain = var("LANGUAGE").unwrap_or_default();
+    for locale in chain.split(':').chain(std::iter::once(locale.as_str())) {
+        // The encoding isn't part of the key
+        let (locale, modifier) = match locale.split_once('@') {
+            Some((x, modifier)) => (x, Some(modifier)),
+            None => (locale, None),
+        };
+        let locale = locale.split('.').next().unwrap_or_default();
+        let (lang, country) = match locale.split_once('_') {
+            Some((lang, country)) => (lang, Some(country)),
+            None => (locale, None),
+        };
+        if lang.is_empty() {
+            continue;
+        }
+        let variants = [
+            country
+                .zip(modifier)
+                .map(|(c, m)| format!("{}_{}@{}", lang, c, m)),
+            country.map(|c| format!("{}_{}", lang, c)),
+            modifier.map(|m| format!("{}@{}", lang, m)),
+            Some(lang.to_string()),
+        ];
+        for variant in variants.into_iter().flatten() {
+            if !suffixes.contains(&variant) {
+                suffixes.push(variant);
+            }
+        }
+    }
+    suffixes
+}
+
+/// Returns the basename of the program an `Exec` value runs, skipping an
+/// `env VAR=value` prefix.
+pub fn exec_binary(exec: &str) -> Option<String> {
+    let program = exec_program(exec)?;
+    let binary = Path::new(&program).file_name()?.to_string_lossy().to_string();
+    Some(binary)
+}
+
+/// The program `Exec` runs as written, a name or a path, skipping `env`.
+pub fn exec_program(exec: &str) -> Option<String> {
+    let args = split_exec(exec);
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(|x| x.as_str()) == Some("env") {
+        args.next();
+        while args.peek().filter(|x| x.contains('=')).is_some() {
+            args.next();
+        }
+    }
+    args.next()
+}
+
+/// Handles the escape sequences allowed in string values.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c) => {
+                // Keep the backslash for escapes that are
+                // meaningful to the Exec quoting rules.
+                if c != '\\' {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Splits an `Exec` value into arguments according to the quoting rules
+/// of the spec: arguments are separated by spaces, double quotes group,
+/// and inside quotes a backslash escapes `"`, `` ` ``, `$` and `\`.
+fn split_exec(exec: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            '\\' if quoted => {
+                if let Some(c) = chars.next() {
+                    current.push(c);
+                }
+            }
+            ' ' | '\t' if !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            _ => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
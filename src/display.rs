@@ -0,0 +1,92 @@
+//! Output layouts for `dmenu_drun display`: kanshi profiles on Wayland,
+//! autorandr profiles or else the modes xrandr knows about on X11.
+use std::process::{Command, Stdio};
+
+use crate::launch::which;
+
+/// A layout as shown in the menu, with the command that applies it.
+pub type Layout = (String, Vec<String>);
+
+pub fn layouts() -> Vec<Layout> {
+    if crate::menu::is_wayland() {
+        return kanshi();
+    }
+    if which("autorandr").is_some() {
+        let profiles = autorandr();
+        if !profiles.is_empty() {
+            return profiles;
+        }
+    }
+    xrandr()
+}
+
+fn output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map(|x| String::from_utf8_lossy(&x.stdout).to_string())
+        .unwrap_or_default()
+}
+
+fn argv(args: &[&str]) -> Vec<String> {
+    args.iter().map(ToString::to_string).collect()
+}
+
+/// The named profiles in kanshi's config, switched to with kanshictl.
+fn kanshi() -> Vec<Layout> {
+    let config = dirs::config_dir()
+        .unwrap_or_default()
+        .join("kanshi")
+        .join("config");
+    std::fs::read_to_string(config)
+        .unwrap_or_default()
+        .lines()
+        // `profile docked {`, unnamed profiles can't be switched to
+        .filter_map(|x| x.trim().strip_prefix("profile "))
+        .filter_map(|x| x.trim_end_matches('{').split_whitespace().next())
+        .map(|name| (name.to_string(), argv(&["kanshictl", "switch", name])))
+        .collect()
+}
+
+/// The profiles saved with `autorandr --save`.
+fn autorandr() -> Vec<Layout> {
+    output("autorandr", &["--list"])
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|name| (name.to_string(), argv(&["autorandr", "--load", name])))
+        .collect()
+}
+
+/// Every mode of every connected output, and turning the output off.
+fn xrandr() -> Vec<Layout> {
+    let mut layouts = Vec::new();
+    let mut current = None;
+    for line in output("xrandr", &["--query"]).lines() {
+        // `HDMI-1 connected 1920x1080+0+0 ...` starts an output, its modes
+        // follow indented: `   1920x1080     60.00*+  50.00`
+        if !line.starts_with(' ') {
+            current = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [output, "connected", ..] => {
+                    layouts.push((
+                        format!("{} off", output),
+                        argv(&["xrandr", "--output", output, "--off"]),
+                    ));
+                    Some(output.to_string())
+                }
+                _ => None,
+            };
+            continue;
+        }
+        let (Some(output), Some(mode)) = (&current, line.split_whitespace().next()) else {
+            continue;
+        };
+        layouts.push((
+            format!("{} {}", output, mode),
+            argv(&["xrandr", "--output", output, "--mode", mode]),
+        ));
+    }
+    layouts
+}
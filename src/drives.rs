@@ -0,0 +1,122 @@
+//! Removable drives for `dmenu_drun drives`: listed with lsblk, mounted
+//! and unmounted through udisks2 with udisksctl, so no root is needed.
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+use crate::Result;
+
+#[derive(Clone, Debug)]
+pub struct Drive {
+    /// The device, like `/dev/sdb1`
+    pub path: String,
+    pub label: Option<String>,
+    pub size: String,
+    pub mountpoint: Option<String>,
+}
+
+impl Drive {
+    /// How the drive is shown in the menu.
+    pub fn display(&self) -> String {
+        let name = match &self.label {
+            Some(label) => format!("{} ({})", label, self.path),
+            None => self.path.clone(),
+        };
+        match &self.mountpoint {
+            Some(mountpoint) => crate::tr!("{} {} — mounted at {}", name, self.size, mountpoint),
+            None => crate::tr!("{} {} — not mounted", name, self.size),
+        }
+    }
+}
+
+/// The filesystems on hotpluggable or removable devices.
+pub fn list() -> Result<Vec<Drive>> {
+    let output = Command::new("lsblk")
+        .args(["-P", "-o", "PATH,LABEL,SIZE,MOUNTPOINT,FSTYPE,HOTPLUG,RM"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| crate::tr!("Could not run lsblk: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(pairs)
+        .filter(|x| {
+            let get = |k| x.get(k).map(String::as_str).unwrap_or_default();
+            !get("FSTYPE").is_empty() && (get("HOTPLUG") == "1" || get("RM") == "1")
+        })
+        .map(|mut x| {
+            let mut take = |k| x.remove(k).filter(|x: &String| !x.is_empty());
+            Drive {
+                path: take("PATH").unwrap_or_default(),
+                label: take("LABEL"),
+                size: take("SIZE").unwrap_or_default(),
+                mountpoint: take("MOUNTPOINT"),
+            }
+        })
+        .collect())
+}
+
+/// Parses a line of `lsblk -P`: `PATH="/dev/sdb1" LABEL="My\x20stick"`.
+fn pairs(line: &str) -> HashMap<String, String> {
+    let mut pairs = HashMap::new();
+    let mut rest = line;
+    while let Some((key, value)) = rest.split_once("=\"") {
+        let Some(end) = value.find('"') else {
+            break;
+        };
+        pairs.insert(key.trim().to_string(), unescape(&value[..end]));
+        rest = &value[end + 1..];
+    }
+    pairs
+}
+
+/// Undoes lsblk's `\xNN` escapes.
+fn unescape(s: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = s.as_bytes();
+    while let Some((&c, tail)) = rest.split_first() {
+        let byte = tail
+            .strip_prefix(b"x")
+            .filter(|_| c == b'\\')
+            .and_then(|x| std::str::from_utf8(x.get(..2)?).ok())
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match byte {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(c);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// `udisksctl <action> -b <device>`, where action is `mount`, `unmount` or
+/// `power-off`.
+pub fn udisksctl(action: &str, drive: &Drive) -> Command {
+    let mut command = Command::new("udisksctl");
+    command.args([action, "--no-user-interaction", "-b", &drive.path]);
+    command
+}
+
+/// Mounts `drive`, returning where.
+pub fn mount(drive: &Drive) -> Result<String> {
+    let output = udisksctl("mount", drive)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| crate::tr!("Could not run udisksctl: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // `Mounted /dev/sdb1 at /run/media/me/STICK`
+    match stdout.trim().split_once(" at ") {
+        Some((_, mountpoint)) if output.status.success() => {
+            Ok(mountpoint.trim_end_matches('.').to_string())
+        }
+        _ => Err(crate::tr!(
+            "Could not mount {}: {}",
+            drive.path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into()),
+    }
+}
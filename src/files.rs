@@ -0,0 +1,46 @@
+//! Finding files for `dmenu_drun files`, with fd or else plocate/locate.
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::config::CONFIG;
+use crate::launch::which;
+use crate::Result;
+
+/// Files under `root` in the `[files]` section (default `~`) whose path
+/// matches `query`, or all of them. At most `max_results` (default 10000)
+/// are returned.
+pub fn search(query: Option<&str>) -> Result<Vec<PathBuf>> {
+    let root = crate::expand_tilde(CONFIG.get("files", "root").unwrap_or("~"));
+    let limit = CONFIG
+        .get("files", "max_results")
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(10000)
+        .to_string();
+    let mut command = if let Some(fd) = ["fd", "fdfind"].into_iter().find(|x| which(x).is_some()) {
+        let mut command = Command::new(fd);
+        command.args(["--type", "f", "--absolute-path", "--max-results", &limit]);
+        // An empty pattern matches every file
+        command.args(["--", query.unwrap_or(""), &root]);
+        command
+    } else if let Some(locate) = ["plocate", "locate"]
+        .into_iter()
+        .find(|x| which(x).is_some())
+    {
+        let mut command = Command::new(locate);
+        command.args(["--ignore-case", "--limit", &limit]);
+        // Files outside of `root` are filtered out below
+        command.arg(query.unwrap_or(&root));
+        command
+    } else {
+        return Err(crate::tr!("Searching files needs fd, plocate or locate").into());
+    };
+    let output = command
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(PathBuf::from)
+        .filter(|x| x.starts_with(&root))
+        .collect())
+}
@@ -0,0 +1,66 @@
+//! Raising a window of an application that's already running, for entries
+//! in the `[focus]` section, instead of starting it again. Windows are
+//! found by their class (the app id on Wayland), which is the desktop
+//! file's `StartupWMClass` if it has one, else the name of its binary.
+use std::process::{Command, Stdio};
+
+use crate::launch::which;
+
+/// Focuses a window of `class`, ignoring case. Returns whether there was
+/// one.
+pub fn focus(class: &str) -> bool {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let pattern = format!("(?i)^{}$", escape(class));
+        // Wayland windows have an app id, XWayland ones a class
+        return ["app_id", "class"]
+            .iter()
+            .any(|x| succeeds("swaymsg", &[&format!("[{}=\"{}\"] focus", x, pattern)]));
+    }
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        // `hyprctl dispatch` succeeds whether a window was found or not
+        let Some(found) = output("hyprctl", &["clients"])
+            .lines()
+            .filter_map(|x| x.trim().strip_prefix("class: "))
+            .find(|x| x.eq_ignore_ascii_case(class))
+            .map(ToString::to_string)
+        else {
+            return false;
+        };
+        let window = format!("class:^({})$", escape(&found));
+        return succeeds("hyprctl", &["dispatch", "focuswindow", &window]);
+    }
+    if !crate::menu::is_wayland() && which("wmctrl").is_some() {
+        return succeeds("wmctrl", &["-x", "-a", class]);
+    }
+    false
+}
+
+/// Escapes `s` to be matched literally in a regular expression.
+fn escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| {
+            let special = "\\.+*?()|[]{}^$\"".contains(c);
+            special.then_some('\\').into_iter().chain([c])
+        })
+        .collect()
+}
+
+fn succeeds(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|x| x.success())
+}
+
+fn output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map(|x| String::from_utf8_lossy(&x.stdout).to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::CONFIG;
+use crate::Result;
+
+lazy_static::lazy_static! {
+    /// Where the history was kept before it was namespaced, read until
+    /// there's a history of our own.
+    static ref SHARED_HISTORY_PATH: PathBuf = dirs::data_local_dir()
+        .unwrap_or_default()
+        .join("dmenu_drun")
+        .join("history");
+    /// Where dmenu kept its history before it was namespaced, copied to
+    /// ours until we have one.
+    static ref SHARED_DMENU_HISTFILE: PathBuf =
+        PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".dmenu_drun_histfile");
+    /// `half_life` and `buckets` from `[frecency]`, see `History::frecency`
+    static ref FRECENCY: (u64, Vec<(u64, f64)>) = (
+        CONFIG
+            .get("frecency", "half_life")
+            .and_then(parse_duration)
+            .filter(|x| *x > 0)
+            .unwrap_or(14 * DAY),
+        CONFIG
+            .get("frecency", "buckets")
+            .unwrap_or("1d:4 7d:2 30d:1")
+            .split_whitespace()
+            .filter_map(|x| {
+                let (age, weight) = x.split_once(':')?;
+                Some((parse_duration(age)?, weight.parse::<f64>().ok()?))
+            })
+            .collect(),
+    );
+}
+
+/// How many argument lines are remembered per executable.
+const MAX_ARGS: usize = 20;
+
+/// How long an unmatched query waits for the user to pick what they meant.
+const PENDING_TIMEOUT: u64 = 120;
+
+/// Usage data kept between runs, stored as tab separated records:
+/// ```text
+/// alias   ff      firefox.desktop
+/// pending ff      1650000000
+/// used    firefox.desktop 1650000000      42
+/// day     19097   5
+/// args    mpv     --fs ~/video.mkv
+/// pinned  firefox.desktop
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    /// Learned abbreviations → executable or desktop file name
+    pub aliases: HashMap<String, String>,
+    /// A typed query that didn't match anything, with the time it was typed
+    pub pending: Option<(String, u64)>,
+    /// Executable or desktop file name → when it was last launched
+    pub used: HashMap<String, u64>,
+    /// Executable or desktop file name → how often it was launched
+    pub launches: HashMap<String, u64>,
+    /// Days since the epoch → how many launches there were that day
+    pub days: BTreeMap<u64, u64>,
+    /// Executable → arguments typed for it in `[ask_args]`'s menu, most
+    /// recent first
+    pub args: HashMap<String, Vec<String>>,
+    /// Executables, desktop file names or names of other entries pinned
+    /// from the menu, in the order they were pinned
+    pub pinned: Vec<String>,
+    /// `--history-file` or `$DMENU_DRUN_HISTORY_FILE`, if given
+    file: Option<PathBuf>,
+    /// What changed since loading, replayed onto the history on disk when
+    /// saving, so instances running at the same time don't lose launches.
+    changes: Vec<Change>,
+}
+
+#[derive(Clone, Debug)]
+enum Change {
+    /// An entry was launched at a time
+    Learn(String, u64),
+    /// An executable was launched with arguments
+    LearnArgs(String, String),
+    /// A query matched nothing at a time
+    Pending(String, u64),
+    /// An entry was pinned, or unpinned if it was pinned already
+    TogglePin(String),
+    Merge(Box<History>),
+}
+
+/// How to order the menu by history, see `History::rank`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+    /// Most recently launched first
+    Recent,
+    /// Most launched first, weighed by how recently, see `History::frecency`
+    Frecency,
+}
+
+impl Sort {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "recent" => Some(Self::Recent),
+            "frecency" => Some(Self::Frecency),
+            _ => None,
+        }
+    }
+}
+
+/// Our history, at `file` if that was given.
+fn history_path(file: Option<&Path>) -> PathBuf {
+    file.map_or_else(
+        || crate::config::namespaced(SHARED_HISTORY_PATH.clone()),
+        Path::to_path_buf,
+    )
+}
+
+/// The history dmenu keeps itself, with `-H`. Kept next to our history
+/// when that's at `file`.
+fn dmenu_histfile(file: Option<&Path>) -> PathBuf {
+    match file {
+        Some(path) => path.with_extension("dmenu"),
+        None => crate::config::namespaced(SHARED_DMENU_HISTFILE.clone()),
+    }
+}
+
+impl History {
+    /// Reads our history, from `file` if given (`--history-file`), which
+    /// it's saved to as well.
+    pub fn load(file: Option<&Path>) -> Self {
+        let read = std::fs::read_to_string(history_path(file));
+        let read = match read {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && file.is_none() => {
+                std::fs::read_to_string(&*SHARED_HISTORY_PATH)
+            }
+            x => x,
+        };
+        let mut history = read.map(|x| Self::parse(&x)).unwrap_or_default();
+        history.file = file.map(Path::to_path_buf);
+        history
+    }
+
+    /// The history dmenu keeps itself, see `menu::Backend::command`. It
+    /// starts as a copy of the shared one, which dmenu would otherwise
+    /// forget.
+    pub fn dmenu_histfile(&self) -> PathBuf {
+        let path = dmenu_histfile(self.file.as_deref());
+        if self.file.is_none() && !path.exists() && SHARED_DMENU_HISTFILE.exists() {
+            if let Err(e) = std::fs::copy(&*SHARED_DMENU_HISTFILE, &path) {
+                eprintln!("dmenu_drun: could not copy dmenu's history: {}", e);
+            }
+        }
+        path
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let mut history = Self::default();
+        for line in s.lines() {
+            match line.split('\t').collect::<Vec<_>>()[..] {
+                ["alias", k, v] => {
+                    history.aliases.insert(k.to_string(), v.to_string());
+                }
+                ["pending", query, time] => {
+                    history.pending = time.parse().ok().map(|x| (query.to_string(), x));
+                }
+                ["used", entry, time, ref count @ ..] => {
+                    if let Ok(time) = time.parse() {
+                        history.used.insert(entry.to_string(), time);
+                    }
+                    let count = count.first().and_then(|x| x.parse().ok()).unwrap_or(1);
+                    history.launches.insert(entry.to_string(), count);
+                }
+                ["args", entry, args] => {
+                    history
+                        .args
+                        .entry(entry.to_string())
+                        .or_default()
+                        .push(args.to_string());
+                }
+                ["pinned", entry] => history.pinned.push(entry.to_string()),
+                ["day", day, count] => {
+                    if let (Ok(day), Ok(count)) = (day.parse(), count.parse()) {
+                        history.days.insert(day, count);
+                    }
+                }
+                _ => {}
+            }
+        }
+        history
+    }
+
+    /// Empties our history and dmenu's. They're left empty rather than
+    /// removed, so the shared ones aren't read again.
+    pub fn clear(file: Option<&Path>) -> Result {
+        let path = history_path(file);
+        let _lock = lock(&path)?;
+        write_atomically(&path, "")?;
+        write_atomically(&dmenu_histfile(file), "")
+    }
+
+    /// Keeps the `history_size` (default 1000) most recently launched
+    /// entries from the `[general]` section, and the abbreviations for them.
+    /// Launches per day are kept for a year.
+    fn trim(&mut self) {
+        let today = now() / DAY;
+        self.days.retain(|day, _| today.saturating_sub(*day) < 365);
+        let size = CONFIG
+            .get("general", "history_size")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(1000);
+        if self.used.len() <= size {
+            return;
+        }
+        let mut used = self.used.drain().collect::<Vec<_>>();
+        used.sort_by_key(|x| std::cmp::Reverse(x.1));
+        used.truncate(size);
+        self.used = used.into_iter().collect();
+        self.aliases.retain(|_, v| self.used.contains_key(v));
+        self.launches.retain(|k, _| self.used.contains_key(k));
+        self.args.retain(|k, _| self.used.contains_key(k));
+    }
+
+    /// Writes the changes made since loading to the history on disk, which
+    /// another instance may have changed meanwhile. The file is locked while
+    /// it's read and replaced, and replaced as a whole, so it's never half
+    /// written.
+    pub fn save(&mut self) -> Result {
+        let path = history_path(self.file.as_deref());
+        let _lock = lock(&path)?;
+        let mut history = Self::load(self.file.as_deref());
+        for change in &self.changes {
+            history.apply(change);
+        }
+        history.trim();
+        write_atomically(&path, &history.to_string())?;
+        *self = history;
+        Ok(())
+    }
+
+    fn change(&mut self, change: Change) {
+        self.apply(&change);
+        self.changes.push(change);
+    }
+
+    fn apply(&mut self, change: &Change) {
+        match change {
+            Change::Learn(entry, time) => {
+                self.used.insert(entry.to_string(), *time);
+                *self.launches.entry(entry.to_string()).or_default() += 1;
+                *self.days.entry(time / DAY).or_default() += 1;
+                if let Some((query, pending)) = self.pending.take() {
+                    if time.saturating_sub(pending) <= PENDING_TIMEOUT && query != *entry {
+                        self.aliases.insert(query, entry.to_string());
+                    }
+                }
+            }
+            Change::LearnArgs(entry, args) => {
+                let history = self.args.entry(entry.to_string()).or_default();
+                history.retain(|x| x != args);
+                history.insert(0, args.to_string());
+                history.truncate(MAX_ARGS);
+            }
+            Change::Pending(query, time) => self.pending = Some((query.to_string(), *time)),
+            Change::TogglePin(entry) => {
+                if self.pinned.contains(entry) {
+                    self.pinned.retain(|x| x != entry);
+                } else {
+                    self.pinned.push(entry.to_string());
+                }
+            }
+            Change::Merge(other) => self.merge_history(*other.clone()),
+        }
+    }
+
+    /// Remembers a query that matched nothing, so it can be learned as an
+    /// abbreviation for what the user picks next.
+    pub fn set_pending(&mut self, query: &str) {
+        self.change(Change::Pending(query.to_string(), now()));
+    }
+
+    /// Called after launching `entry`: records when it was used, and if the
+    /// previous query matched nothing and was typed recently, it becomes an
+    /// alias for `entry`.
+    pub fn learn(&mut self, entry: &str) {
+        self.change(Change::Learn(entry.to_string(), now()));
+    }
+
+    /// Remembers the arguments `entry` was launched with, keeping the last
+    /// `MAX_ARGS`.
+    pub fn learn_args(&mut self, entry: &str, args: &str) {
+        self.change(Change::LearnArgs(entry.to_string(), args.to_string()));
+    }
+
+    /// Pins `entry` to the top of the menu, or unpins it. Returns whether
+    /// it's pinned now.
+    pub fn toggle_pin(&mut self, entry: &str) -> bool {
+        self.change(Change::TogglePin(entry.to_string()));
+        self.pinned.iter().any(|x| x == entry)
+    }
+
+    /// Adds the history of another machine to this one: launch counts are
+    /// summed, the newest time of use is kept, and our aliases win over
+    /// theirs. Merging the same history twice counts its launches twice.
+    pub fn merge(&mut self, other: History) {
+        self.change(Change::Merge(Box::new(other)));
+    }
+
+    fn merge_history(&mut self, other: History) {
+        for (entry, time) in other.used {
+            let used = self.used.entry(entry).or_default();
+            *used = (*used).max(time);
+        }
+        for (entry, count) in other.launches {
+            *self.launches.entry(entry).or_default() += count;
+        }
+        for (day, count) in other.days {
+            *self.days.entry(day).or_default() += count;
+        }
+        for (query, entry) in other.aliases {
+            self.aliases.entry(query).or_insert(entry);
+        }
+        for (entry, args) in other.args {
+            let history = self.args.entry(entry).or_default();
+            for args in args {
+                if !history.contains(&args) {
+                    history.push(args);
+                }
+            }
+            history.truncate(MAX_ARGS);
+        }
+        for entry in other.pinned {
+            if !self.pinned.contains(&entry) {
+                self.pinned.push(entry);
+            }
+        }
+    }
+
+    /// How high `entry` should rank with `sort`, higher is better.
+    pub fn rank(&self, entry: &str, sort: Sort) -> f64 {
+        match sort {
+            Sort::Recent => self.used.get(entry).copied().unwrap_or_default() as f64,
+            Sort::Frecency => self.frecency(entry),
+        }
+    }
+
+    /// The amount of launches, weighed by how long ago the last one was
+    /// with the `[frecency]` section: `half_life` halves the score every
+    /// period (default `14d`), and `buckets` multiplies it by the weight
+    /// of the first age it's within, e.g. `1d:4 7d:2 30d:1` (the default).
+    /// Older entries get the last weight.
+    fn frecency(&self, entry: &str) -> f64 {
+        let Some(time) = self.used.get(entry) else {
+            return 0.;
+        };
+        let age = now().saturating_sub(*time);
+        let (half_life, buckets) = &*FRECENCY;
+        let weight = buckets
+            .iter()
+            .find(|x| age <= x.0)
+            .or(buckets.last())
+            .map_or(1., |x| x.1);
+        let count = self.launches.get(entry).copied().unwrap_or(1) as f64;
+        count * weight * 0.5f64.powf(age as f64 / *half_life as f64)
+    }
+}
+
+impl std::fmt::Display for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (k, v) in &self.aliases {
+            writeln!(f, "alias\t{}\t{}", k, v)?;
+        }
+        if let Some((query, time)) = &self.pending {
+            writeln!(f, "pending\t{}\t{}", query, time)?;
+        }
+        for (entry, time) in &self.used {
+            let count = self.launches.get(entry).copied().unwrap_or(1);
+            writeln!(f, "used\t{}\t{}\t{}", entry, time, count)?;
+        }
+        for (entry, args) in &self.args {
+            for args in args {
+                writeln!(f, "args\t{}\t{}", entry, args)?;
+            }
+        }
+        for (day, count) in &self.days {
+            writeln!(f, "day\t{}\t{}", day, count)?;
+        }
+        for entry in &self.pinned {
+            writeln!(f, "pinned\t{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Locks the history at `path` against other instances until the file is
+/// dropped.
+fn lock(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(".lock");
+    let file = File::create(path)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(file)
+}
+
+/// Writes `contents` to a temporary file next to `path` first, then moves
+/// it over `path`, so a crash leaves either the old or the new file.
+fn write_atomically(path: &Path, contents: &str) -> Result {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = PathBuf::from(name);
+    let mut file = File::create(&temp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+pub const DAY: u64 = 24 * 60 * 60;
+
+/// Parses durations like `30m`, `12h`, `7d` or `2w`, plain numbers are
+/// seconds.
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => DAY,
+        "w" => 7 * DAY,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// Formats how long ago `time` was, e.g. `2d ago`.
+pub fn ago(time: u64) -> String {
+    match now().saturating_sub(time) {
+        x if x < 60 => crate::tr!("just now"),
+        x if x < 60 * 60 => crate::tr!("{}m ago", x / 60),
+        x if x < 24 * 60 * 60 => crate::tr!("{}h ago", x / 60 / 60),
+        x => crate::tr!("{}d ago", x / 24 / 60 / 60),
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,115 @@
+//! Translations of dmenu_drun's own messages. They're read from gettext
+//! `.po` files named after the locale, in `dmenu_drun/locale` in the XDG
+//! data directories, e.g. `~/.local/share/dmenu_drun/locale/nl.po`. Like
+//! gettext, messages are looked up by their English text.
+use std::collections::HashMap;
+
+use crate::config::data_dirs;
+use crate::desktop::LOCALE_SUFFIXES;
+
+lazy_static::lazy_static! {
+    static ref CATALOG: HashMap<String, String> = load();
+}
+
+/// Translates `msgid`, then replaces every `{}` in it with the next of
+/// `args`.
+#[macro_export]
+macro_rules! tr {
+    ($msgid:literal) => {
+        $crate::i18n::translate($msgid, &[])
+    };
+    ($msgid:literal, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::translate($msgid, &[$(&$arg.to_string()),+])
+    };
+}
+
+pub fn translate(msgid: &str, args: &[&str]) -> String {
+    let mut message = CATALOG.get(msgid).map_or(msgid, String::as_str);
+    let mut out = String::with_capacity(message.len());
+    let mut args = args.iter();
+    while let Some((before, after)) = message.split_once("{}") {
+        out.push_str(before);
+        out.push_str(args.next().unwrap_or(&""));
+        message = after;
+    }
+    out.push_str(message);
+    out
+}
+
+/// Loads the catalog for the best matching locale.
+fn load() -> HashMap<String, String> {
+    LOCALE_SUFFIXES
+        .iter()
+        .flat_map(|locale| {
+            data_dirs()
+                .into_iter()
+                .map(move |x| x.join("dmenu_drun/locale").join(format!("{}.po", locale)))
+        })
+        .find_map(|x| std::fs::read_to_string(x).ok())
+        .map(|x| parse_po(&x))
+        .unwrap_or_default()
+}
+
+/// Reads the `msgid`/`msgstr` pairs of a `.po` file. Contexts and plural
+/// forms are skipped, as are untranslated messages.
+fn parse_po(s: &str) -> HashMap<String, String> {
+    let mut catalog = HashMap::new();
+    let mut pair: Option<(String, String)> = None;
+    // Whether continuation lines belong to the msgid or the msgstr
+    let mut in_msgid = true;
+    let mut has_context = false;
+    let mut flush = |pair: Option<(String, String)>| {
+        if let Some((id, msg)) = pair.filter(|x| !x.0.is_empty() && !x.1.is_empty()) {
+            catalog.insert(id, msg);
+        }
+    };
+    for line in s.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            flush(pair.take());
+            pair = (!has_context).then(|| (unquote(rest), String::new()));
+            in_msgid = true;
+            has_context = false;
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            if let Some(pair) = &mut pair {
+                pair.1 = unquote(rest);
+            }
+            in_msgid = false;
+        } else if line.starts_with('"') {
+            if let Some(pair) = &mut pair {
+                let field = if in_msgid { &mut pair.0 } else { &mut pair.1 };
+                field.push_str(&unquote(line));
+            }
+        } else {
+            // msgctxt, msgid_plural or msgstr[n]
+            flush(pair.take());
+            has_context = line.starts_with("msgctxt");
+        }
+    }
+    flush(pair);
+    catalog
+}
+
+/// Strips the quotes around a `.po` string and handles its escapes.
+fn unquote(s: &str) -> String {
+    let s = s.trim();
+    let s = s.strip_prefix('"').unwrap_or(s);
+    let s = s.strip_suffix('"').unwrap_or(s);
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
@@ -0,0 +1,199 @@
+//! Finds the file of an `Icon` name in the icon theme, for menus that need
+//! image paths.
+//! See also: https://specifications.freedesktop.org/icon-theme-spec/latest/
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::{data_dirs, Config, CONFIG};
+
+const EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+/// A directory of a theme, from the theme's `index.theme`.
+#[derive(Clone, Debug)]
+struct Directory {
+    /// `Fixed`, `Scalable` or `Threshold`
+    kind: String,
+    size: u32,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+    /// Where the directory exists, with the files in it
+    files: Vec<(PathBuf, HashSet<String>)>,
+}
+
+impl Directory {
+    fn matches(&self, size: u32) -> bool {
+        match self.kind.as_str() {
+            "Fixed" => self.size == size,
+            "Scalable" => (self.min_size..=self.max_size).contains(&size),
+            _ => self.size.abs_diff(size) <= self.threshold,
+        }
+    }
+
+    fn distance(&self, size: u32) -> u32 {
+        let (min, max) = match self.kind.as_str() {
+            "Fixed" => (self.size, self.size),
+            "Scalable" => (self.min_size, self.max_size),
+            _ => (
+                self.size.saturating_sub(self.threshold),
+                self.size + self.threshold,
+            ),
+        };
+        min.saturating_sub(size).max(size.saturating_sub(max))
+    }
+
+    /// The file of `name` in this directory.
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        self.files.iter().find_map(|(dir, files)| {
+            EXTENSIONS
+                .iter()
+                .map(|ext| format!("{}.{}", name, ext))
+                .find(|x| files.contains(x))
+                .map(|x| dir.join(x))
+        })
+    }
+}
+
+/// The icon theme and the themes it inherits from, ending with hicolor.
+pub struct Icons {
+    themes: Vec<Vec<Directory>>,
+    size: u32,
+}
+
+/// Where themes are installed, most important first.
+fn base_dirs() -> Vec<PathBuf> {
+    dirs::home_dir()
+        .map(|x| x.join(".icons"))
+        .into_iter()
+        .chain(data_dirs().into_iter().map(|x| x.join("icons")))
+        .collect()
+}
+
+/// `icon_theme` in the `[menu]` section, or else GTK's theme.
+fn theme_name() -> String {
+    if let Some(theme) = CONFIG.get("menu", "icon_theme") {
+        return theme.to_string();
+    }
+    let settings = dirs::config_dir()
+        .unwrap_or_default()
+        .join("gtk-3.0")
+        .join("settings.ini");
+    std::fs::read_to_string(settings)
+        .ok()
+        .and_then(|x| {
+            Config::parse(&x)
+                .get("Settings", "gtk-icon-theme-name")
+                .map(ToString::to_string)
+        })
+        .unwrap_or_else(|| "hicolor".to_string())
+}
+
+impl Icons {
+    /// Loads the theme for icons of `icon_size` (default 32) in the
+    /// `[menu]` section.
+    pub fn load() -> Self {
+        let size = CONFIG
+            .get("menu", "icon_size")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(32);
+        let bases = base_dirs();
+        let mut names = vec![theme_name()];
+        let mut themes = Vec::new();
+        let mut i = 0;
+        while i < names.len() || !names.iter().any(|x| x == "hicolor") {
+            // hicolor is always looked at last
+            if i == names.len() {
+                names.push("hicolor".to_string());
+            }
+            let name = names[i].clone();
+            i += 1;
+            let Some(index) = bases
+                .iter()
+                .find_map(|x| std::fs::read_to_string(x.join(&name).join("index.theme")).ok())
+            else {
+                continue;
+            };
+            let index = Config::parse(&index);
+            let inherits = index.get("Icon Theme", "Inherits").unwrap_or_default();
+            for parent in inherits.split(',').map(str::trim) {
+                if !parent.is_empty() && !names.iter().any(|x| x == parent) {
+                    names.push(parent.to_string());
+                }
+            }
+            themes.push(load_directories(&index, &bases, &name));
+        }
+        Self { themes, size }
+    }
+
+    /// The file of the icon called `name`, or `name` itself if it's a path.
+    /// Icons that fit the size are preferred, from the theme first, then
+    /// the closest in size. Falls back to `/usr/share/pixmaps`.
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        if Path::new(name).is_absolute() {
+            return Path::new(name).is_file().then(|| PathBuf::from(name));
+        }
+        let name = EXTENSIONS
+            .iter()
+            .find_map(|ext| name.strip_suffix(&format!(".{}", ext)))
+            .unwrap_or(name);
+        for directories in &self.themes {
+            let exact = directories
+                .iter()
+                .filter(|x| x.matches(self.size))
+                .find_map(|x| x.find(name));
+            let closest = || {
+                directories
+                    .iter()
+                    .filter_map(|x| Some((x.distance(self.size), x.find(name)?)))
+                    .min_by_key(|x| x.0)
+                    .map(|x| x.1)
+            };
+            if let Some(path) = exact.or_else(closest) {
+                return Some(path);
+            }
+        }
+        EXTENSIONS
+            .iter()
+            .map(|ext| Path::new("/usr/share/pixmaps").join(format!("{}.{}", name, ext)))
+            .find(|x| x.is_file())
+    }
+}
+
+/// The directories of theme `name` for the normal scale, listing the files
+/// in each once, so looking up many icons doesn't take many lookups.
+fn load_directories(index: &Config, bases: &[PathBuf], name: &str) -> Vec<Directory> {
+    let get = |section: &str, key: &str| index.get(section, key).map(ToString::to_string);
+    let number = |section: &str, key: &str| get(section, key).and_then(|x| x.parse::<u32>().ok());
+    get("Icon Theme", "Directories")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .filter(|x| number(x, "Scale").unwrap_or(1) == 1)
+        .filter_map(|subdir| {
+            let size = number(subdir, "Size")?;
+            let files = bases
+                .iter()
+                .map(|x| x.join(name).join(subdir))
+                .filter(|x| x.is_dir())
+                .map(|dir| {
+                    let files = std::fs::read_dir(&dir)
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                        .map(|x| x.file_name().to_string_lossy().to_string())
+                        .collect();
+                    (dir, files)
+                })
+                .collect::<Vec<_>>();
+            (!files.is_empty()).then(|| Directory {
+                kind: get(subdir, "Type").unwrap_or_else(|| "Threshold".to_string()),
+                size,
+                min_size: number(subdir, "MinSize").unwrap_or(size),
+                max_size: number(subdir, "MaxSize").unwrap_or(size),
+                threshold: number(subdir, "Threshold").unwrap_or(2),
+                files,
+            })
+        })
+        .collect()
+}
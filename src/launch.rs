@@ -0,0 +1,810 @@
+use std::collections::HashMap;
+use std::os::unix::prelude::{CommandExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::CONFIG;
+use crate::desktop::DesktopEntry;
+use crate::{Result, PATH_DIRS};
+
+/// Variables a graphical program needs to connect to the user's session.
+/// pkexec and run0 start from a clean environment, so these have to be
+/// forwarded explicitly.
+const SESSION_VARS: [&str; 6] = [
+    "DISPLAY",
+    "XAUTHORITY",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+    "XDG_SESSION_TYPE",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+/// Finds an executable in `$PATH`.
+pub fn which(name: &str) -> Option<PathBuf> {
+    PATH_DIRS
+        .iter()
+        .map(|x| x.join(name))
+        .find(|x| is_executable(x))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or_default()
+}
+
+/// Where programs were found in `$PATH`, kept next to the cache so checking
+/// many of them doesn't look through every directory on each run. Stored as
+/// `name\0index`, the index of the directory in `$PATH` it was found in, or
+/// `name\0` when it wasn't found.
+pub struct ExecCache {
+    /// `None` with `--no-cache`
+    path: Option<PathBuf>,
+    found: HashMap<String, Option<usize>>,
+    changed: bool,
+}
+
+impl ExecCache {
+    /// Reads the saved lookups, dropping those a changed directory in
+    /// `$PATH` could have made wrong: ones that weren't found, and ones found
+    /// in or after the first directory that changed since they were saved.
+    /// Nothing is read without `cache_path`.
+    pub fn load(cache_path: Option<PathBuf>) -> Self {
+        let Some(cache_path) = cache_path else {
+            return Self {
+                path: None,
+                found: HashMap::new(),
+                changed: false,
+            };
+        };
+        let mut path = cache_path.into_os_string();
+        path.push(".exec");
+        let path = PathBuf::from(path);
+        let saved = path.metadata().and_then(|x| x.modified()).ok();
+        let first_changed = PATH_DIRS.iter().position(|x| {
+            let modified = x.metadata().and_then(|x| x.modified()).ok();
+            saved.is_none() || modified > saved
+        });
+        let found = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|x| x.split_once('\0'))
+            .map(|(name, index)| (name.to_string(), index.parse::<usize>().ok()))
+            .filter(|(_, index)| match (first_changed, index) {
+                (None, _) => true,
+                (Some(changed), Some(index)) => index < &changed,
+                (Some(_), None) => false,
+            })
+            .collect();
+        Self {
+            path: Some(path),
+            found,
+            changed: first_changed.is_some(),
+        }
+    }
+
+    /// Whether `program`, a name in `$PATH` or an absolute path, exists.
+    pub fn exists(&mut self, program: &str) -> bool {
+        if program.contains('/') {
+            return is_executable(Path::new(program));
+        }
+        if let Some(index) = self.found.get(program) {
+            return index.is_some();
+        }
+        let index = PATH_DIRS
+            .iter()
+            .position(|x| is_executable(&x.join(program)));
+        self.found.insert(program.to_string(), index);
+        self.changed = true;
+        index.is_some()
+    }
+
+    /// Writes the lookups back if anything was looked up again.
+    pub fn save(&self) -> Result {
+        let Some(path) = self.path.as_ref().filter(|_| self.changed) else {
+            return Ok(());
+        };
+        let lines = self
+            .found
+            .iter()
+            .map(|(name, index)| {
+                let index = index.map(|x| x.to_string()).unwrap_or_default();
+                format!("{}\0{}\n", name, index)
+            })
+            .collect::<String>();
+        std::fs::write(path, lines)?;
+        Ok(())
+    }
+}
+
+/// Wraps `argv` in the method set with `elevate` in the `[general]`
+/// section: `pkexec`, `run0` or `sudo` (with `-A`, asking for the password
+/// with the `askpass` program). Defaults to pkexec, or run0 if polkit's
+/// pkexec isn't installed. `user` is the target user, root if `None`.
+pub fn elevate(argv: &[String], user: Option<&str>) -> Vec<String> {
+    let env = SESSION_VARS
+        .iter()
+        .filter_map(|k| std::env::var(k).ok().map(|v| format!("{}={}", k, v)));
+    let method = CONFIG.get("general", "elevate").unwrap_or_else(|| {
+        if which("pkexec").is_none() && which("run0").is_some() {
+            "run0"
+        } else {
+            "pkexec"
+        }
+    });
+    let mut wrapped = Vec::new();
+    match method {
+        "run0" => {
+            wrapped.push("run0".to_string());
+            if let Some(user) = user {
+                wrapped.push(format!("--user={}", user));
+            }
+            wrapped.extend(env.map(|x| format!("--setenv={}", x)));
+            wrapped.push("--".to_string());
+        }
+        "sudo" => {
+            if let Some(askpass) = CONFIG.get("general", "askpass") {
+                let askpass = crate::expand_tilde(askpass);
+                wrapped.extend(["env".to_string(), format!("SUDO_ASKPASS={}", askpass)]);
+            }
+            wrapped.extend([
+                "sudo".to_string(),
+                "-A".to_string(),
+                format!("--preserve-env={}", SESSION_VARS.join(",")),
+            ]);
+            if let Some(user) = user {
+                wrapped.extend(["-u".to_string(), user.to_string()]);
+            }
+            wrapped.push("--".to_string());
+        }
+        x => {
+            if x != "pkexec" {
+                eprintln!("dmenu_drun: unknown elevation method `{}`, using pkexec", x);
+            }
+            wrapped.push("pkexec".to_string());
+            if let Some(user) = user {
+                wrapped.extend(["--user".to_string(), user.to_string()]);
+            }
+            wrapped.push("env".to_string());
+            wrapped.extend(env);
+        }
+    }
+    wrapped.extend_from_slice(argv);
+    wrapped
+}
+
+/// Starts the commands built for a selection. Launching goes through this,
+/// so the whole flow can be run without actually starting anything.
+pub trait Spawner {
+    fn spawn(&mut self, command: &mut Command) -> Result;
+
+    /// Whether commands are only shown, so nothing should be recorded.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+/// Starts commands for real, see `spawn`.
+pub struct Detached;
+
+impl Spawner for Detached {
+    fn spawn(&mut self, command: &mut Command) -> Result {
+        spawn(command)
+    }
+}
+
+/// Prints commands as shell lines instead of starting them, with the
+/// environment variables that were set on them.
+pub struct DryRun;
+
+impl Spawner for DryRun {
+    fn spawn(&mut self, command: &mut Command) -> Result {
+        apply_environment(command);
+        println!("{}", command_line(command));
+        Ok(())
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+/// `command` as a line that can be pasted into a shell, with the variables
+/// it removes and sets (see `explicit_env`) in front.
+pub fn command_line(command: &Command) -> String {
+    let removed = command
+        .get_envs()
+        .filter(|(_, v)| v.is_none())
+        .flat_map(|(k, _)| ["-u".to_string(), k.to_string_lossy().to_string()])
+        .collect::<Vec<_>>();
+    let removed = (!removed.is_empty())
+        .then(|| std::iter::once("env".to_string()).chain(removed))
+        .into_iter()
+        .flatten();
+    let env = removed.chain(explicit_env(command));
+    let argv = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|x| x.to_string_lossy().to_string());
+    shell_join(&env.chain(argv).collect::<Vec<_>>())
+}
+
+/// Launches on the host when we run inside Flatpak or Snap, where programs
+/// started directly would be confined with us (or not found at all).
+/// Programs go through `flatpak-spawn --host`, URLs passed to xdg-open
+/// through the OpenURI portal. The translated command is handed to the
+/// wrapped spawner.
+pub struct Portal(pub Box<dyn Spawner>);
+
+impl Spawner for Portal {
+    fn spawn(&mut self, command: &mut Command) -> Result {
+        apply_environment(command);
+        match host_command(command) {
+            Some(mut host) => self.0.spawn(&mut host),
+            None => self.0.spawn(command),
+        }
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.0.is_dry_run()
+    }
+}
+
+/// Whether we run inside a Flatpak or Snap sandbox.
+pub fn is_sandboxed() -> bool {
+    std::path::Path::new("/.flatpak-info").exists() || std::env::var_os("SNAP").is_some()
+}
+
+/// See `Portal`. Returns `None` if `command` can be run as is.
+fn host_command(command: &Command) -> Option<Command> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let args = command
+        .get_args()
+        .map(|x| x.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    if let ("xdg-open", [uri]) = (program.as_str(), &args[..]) {
+        if uri.contains("://") && !uri.starts_with("file://") {
+            let quoted = format!("'{}'", uri.replace('\\', "\\\\").replace('\'', "\\'"));
+            let mut portal = Command::new("gdbus");
+            portal.args([
+                "call",
+                "--session",
+                "--dest=org.freedesktop.portal.Desktop",
+                "--object-path=/org/freedesktop/portal/desktop",
+                "--method=org.freedesktop.portal.OpenURI.OpenURI",
+                "",
+                &quoted,
+                "{}",
+            ]);
+            return Some(portal);
+        }
+    }
+    if !std::path::Path::new("/.flatpak-info").exists() {
+        // Snap has no way to reach the host's programs
+        return None;
+    }
+    let mut host = Command::new("flatpak-spawn");
+    host.arg("--host");
+    for (k, v) in command.get_envs() {
+        match v {
+            Some(v) => host.arg(format!(
+                "--env={}={}",
+                k.to_string_lossy(),
+                v.to_string_lossy()
+            )),
+            None => host.arg(format!("--unset-env={}", k.to_string_lossy())),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        host.arg(format!("--directory={}", dir.display()));
+    }
+    host.arg(program).args(args);
+    Some(host)
+}
+
+/// Starts `command` fully detached from us: it's double-forked, so it gets
+/// reparented to init and never becomes a zombie, and it runs in its own
+/// session, so it outlives the menu's hotkey daemon. Returns once the
+/// program has been exec'd, failing if it couldn't be.
+pub fn spawn(command: &mut Command) -> Result {
+    apply_environment(command);
+    let program = command.get_program().to_string_lossy().to_string();
+    unsafe {
+        command.pre_exec(|| {
+            // The intermediate child exits right away, std reaps it below.
+            // The exec error pipe is inherited by the grandchild, so
+            // `spawn` still reports whether the exec succeeded.
+            match libc::fork() {
+                -1 => return Err(std::io::Error::last_os_error()),
+                0 => {}
+                _ => libc::_exit(0),
+            }
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| crate::tr!("Could not start {}: {}", program, e))?;
+    child.wait()?;
+    Ok(())
+}
+
+lazy_static::lazy_static! {
+    /// The variables from `import` in the `[environment]` section
+    static ref IMPORTED: Vec<(String, String)> = import_environment();
+}
+
+/// Reads the space separated sources in `import`, later ones winning:
+/// `systemd` for the environment of the user's service manager, or files
+/// of `KEY=value` lines (optionally with `export`, quotes and `#` comments).
+/// Started from a keybinding, dmenu_drun may lack what a login shell sets,
+/// like `SSH_AUTH_SOCK` or the locale.
+fn import_environment() -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    for source in CONFIG
+        .get("environment", "import")
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        let text = if source == "systemd" {
+            Command::new("systemctl")
+                .args(["--user", "show-environment"])
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()
+                .map(|x| String::from_utf8_lossy(&x.stdout).to_string())
+                .map_err(|e| e.to_string())
+        } else {
+            std::fs::read_to_string(crate::expand_tilde(source)).map_err(|e| e.to_string())
+        };
+        match text {
+            Ok(x) => vars.extend(parse_environment(&x)),
+            Err(e) => eprintln!("dmenu_drun: could not import {}: {}", source, e),
+        }
+    }
+    vars
+}
+
+/// Parses `KEY=value` lines, see `import_environment`. systemd quotes
+/// values with special characters as `$'…'`, with escapes like in C.
+pub fn parse_environment(text: &str) -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((k, v)) = line.split_once('=').filter(|_| !line.starts_with('#')) else {
+            continue;
+        };
+        let v = match v.strip_prefix("$'").and_then(|x| x.strip_suffix('\'')) {
+            Some(x) => unescape(x),
+            None => ['"', '\'']
+                .iter()
+                .find_map(|q| v.strip_prefix(*q)?.strip_suffix(*q))
+                .unwrap_or(v)
+                .to_string(),
+        };
+        vars.push((k.trim().to_string(), v));
+    }
+    vars
+}
+
+/// Undoes the escapes in `$'…'`: `\n`, `\t` and the other C escapes, and
+/// bytes as `\ooo` in octal or `\xHH` in hex.
+fn unescape(s: &str) -> String {
+    let s = s.as_bytes();
+    let mut bytes = Vec::with_capacity(s.len());
+    // Up to `max` digits in `radix` from `start`, `None` if there are none
+    let number = |start: usize, max: usize, radix: u32| {
+        let digits = s[start..]
+            .iter()
+            .take(max)
+            .take_while(|x| (**x as char).is_digit(radix))
+            .count();
+        let text = std::str::from_utf8(&s[start..start + digits]).ok()?;
+        u32::from_str_radix(text, radix).ok().map(|x| (x as u8, digits))
+    };
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] != b'\\' || i + 1 == s.len() {
+            bytes.push(s[i]);
+            i += 1;
+            continue;
+        }
+        let (byte, len) = match s[i + 1] {
+            b'a' => (0x07, 1),
+            b'b' => (0x08, 1),
+            b'e' | b'E' => (0x1b, 1),
+            b'f' => (0x0c, 1),
+            b'n' => (b'\n', 1),
+            b'r' => (b'\r', 1),
+            b't' => (b'\t', 1),
+            b'v' => (0x0b, 1),
+            b'x' => match number(i + 2, 2, 16) {
+                Some((x, digits)) => (x, 1 + digits),
+                None => (b'\\', 0),
+            },
+            b'0'..=b'7' => number(i + 1, 3, 8).unwrap_or_default(),
+            x @ (b'\\' | b'\'' | b'"' | b'?') => (x, 1),
+            // Anything else isn't an escape, the backslash stays
+            _ => (b'\\', 0),
+        };
+        bytes.push(byte);
+        i += 1 + len;
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// The variables set on `command` as `KEY=value`, leaving out the ones
+/// imported by `apply_environment`: that's the whole session environment
+/// with its tokens and sockets, which has no place in logs.
+pub fn explicit_env(command: &Command) -> Vec<String> {
+    let overridden = CONFIG.merged("environment");
+    command
+        .get_envs()
+        .filter_map(|(k, v)| Some((k.to_string_lossy(), v?.to_string_lossy())))
+        .filter(|(k, v)| {
+            overridden.iter().any(|x| x.0 == k)
+                || !IMPORTED.iter().any(|x| x.0 == *k && x.1 == *v)
+        })
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect()
+}
+
+/// Changes the environment launched programs get with the `[environment]`
+/// section: the variables from `import` are set first (see
+/// `import_environment`), then `unset` removes the variables matching its
+/// space separated patterns (e.g. what a terminal leaked into the session,
+/// like `VIRTUAL_ENV PYENV_*`), and every other key is set to its value.
+/// Variables set on `command` itself are left alone.
+pub fn apply_environment(command: &mut Command) {
+    let own = command
+        .get_envs()
+        .map(|(k, _)| k.to_os_string())
+        .collect::<Vec<_>>();
+    let unset = CONFIG.get("environment", "unset").unwrap_or_default();
+    let patterns = unset.split_whitespace().collect::<Vec<_>>();
+    for (k, v) in IMPORTED.iter() {
+        if !own.iter().any(|x| x == k.as_str())
+            && !patterns.iter().any(|x| crate::config::glob_match(x, k))
+        {
+            command.env(k, v);
+        }
+    }
+    for (k, _) in std::env::vars_os() {
+        let name = k.to_string_lossy();
+        if !own.contains(&k) && patterns.iter().any(|x| crate::config::glob_match(x, &name)) {
+            command.env_remove(&k);
+        }
+    }
+    for (k, v) in CONFIG.merged("environment") {
+        if k != "unset" && k != "import" && !own.iter().any(|x| x == k) {
+            command.env(k, v);
+        }
+    }
+}
+
+/// Passes an xdg-activation token to `command` on Wayland, so the program
+/// gets focus instead of opening behind other windows. The token is the one
+/// we were started with, or else what the `activation_token` command in the
+/// `[general]` section prints.
+pub fn pass_activation_token(command: &mut Command) {
+    if !crate::menu::is_wayland() {
+        return;
+    }
+    let token = std::env::var("XDG_ACTIVATION_TOKEN")
+        .ok()
+        .or_else(|| {
+            let output = Command::new("sh")
+                .args(["-c", CONFIG.get("general", "activation_token")?])
+                .stdin(Stdio::null())
+                .output()
+                .ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .filter(|x| !x.is_empty());
+    if let Some(token) = token {
+        // GTK 3 only knows the X11 startup notification variable
+        command
+            .env("XDG_ACTIVATION_TOKEN", &token)
+            .env("DESKTOP_STARTUP_ID", &token);
+    }
+}
+
+/// Builds a `Command` out of an argument vector, which can't be empty.
+pub fn to_command(argv: &[String]) -> Result<Command> {
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| crate::tr!("Nothing to launch"))?;
+    let mut command = Command::new(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// Joins `argv` into a line that can be pasted into a shell.
+pub fn shell_join(argv: &[String]) -> String {
+    argv.iter()
+        .map(|x| {
+            if !x.is_empty()
+                && x.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+            {
+                x.clone()
+            } else {
+                format!("'{}'", x.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The terminal emulator configured with `terminal` in the `[general]`
+/// section, e.g. `alacritty -e`. Defaults to `$TERMINAL -e`, then
+/// `xterm -e`.
+pub fn terminal() -> Vec<String> {
+    let terminal = CONFIG
+        .get("general", "terminal")
+        .map(ToString::to_string)
+        .or_else(|| std::env::var("TERMINAL").ok().map(|x| format!("{} -e", x)))
+        .unwrap_or_else(|| "xterm -e".to_string());
+    terminal
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Builds a command running `argv` in the configured terminal emulator.
+pub fn in_terminal(argv: &[String]) -> Result<Command> {
+    let mut wrapped = terminal();
+    wrapped.extend_from_slice(argv);
+    to_command(&wrapped)
+}
+
+/// Wraps `argv` in the sandbox configured in the `[sandbox]` section for
+/// any of `names`. Values are either `firejail <profile>` or
+/// `bwrap <options...>`.
+pub fn sandbox(argv: Vec<String>, names: &[&str]) -> Vec<String> {
+    let Some(sandbox) = CONFIG.lookup("sandbox", names) else {
+        return argv;
+    };
+    let mut words = sandbox.split_whitespace();
+    let mut wrapped = match (words.next(), words.next()) {
+        (Some("firejail"), Some(profile)) => {
+            vec!["firejail".to_string(), format!("--profile={}", profile)]
+        }
+        (Some("firejail"), None) => vec!["firejail".to_string()],
+        (Some("bwrap"), first) => std::iter::once("bwrap")
+            .chain(first)
+            .chain(words)
+            .map(ToString::to_string)
+            .collect(),
+        _ => {
+            eprintln!("dmenu_drun: unknown sandbox `{}`, ignoring", sandbox);
+            return argv;
+        }
+    };
+    wrapped.push("--".to_string());
+    wrapped.extend(argv);
+    wrapped
+}
+
+/// Runs `argv` on the discrete GPU if any of `names` matches a pattern in
+/// the `[gpu]` section, or `prefers_gpu` is set (`PrefersNonDefaultGPU`).
+/// Values are a wrapper like `prime-run`, or variables like `DRI_PRIME=1`.
+/// `gpu_offload` in `[general]` is used for `prefers_gpu`, defaulting to
+/// prime-run if it's installed and `DRI_PRIME=1` otherwise.
+pub fn offload(argv: Vec<String>, names: &[&str], prefers_gpu: bool) -> Vec<String> {
+    let default = || {
+        CONFIG
+            .get("general", "gpu_offload")
+            .unwrap_or(if which("prime-run").is_some() {
+                "prime-run"
+            } else {
+                "DRI_PRIME=1"
+            })
+    };
+    let Some(wrapper) = CONFIG
+        .lookup("gpu", names)
+        .or_else(|| prefers_gpu.then(default))
+    else {
+        return argv;
+    };
+    let mut wrapped = wrapper
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if wrapped.first().is_some_and(|x| x.contains('=')) {
+        wrapped.insert(0, "env".to_string());
+    }
+    wrapped.extend(argv);
+    wrapped
+}
+
+/// Keeps the machine awake while `argv` runs if any of `names` matches a
+/// pattern in the `[inhibit]` section. Values are what to inhibit, as
+/// passed to `systemd-inhibit --what`, or `true` for `idle:sleep`.
+pub fn inhibit(argv: Vec<String>, names: &[&str]) -> Vec<String> {
+    let Some(what) = CONFIG.lookup("inhibit", names) else {
+        return argv;
+    };
+    let what = match what {
+        "true" => "idle:sleep",
+        x => x,
+    };
+    let mut wrapped = vec![
+        "systemd-inhibit".to_string(),
+        format!("--what={}", what),
+        "--who=dmenu_drun".to_string(),
+        format!(
+            "--why=Running {}",
+            names.first().unwrap_or(&argv[0].as_str())
+        ),
+    ];
+    wrapped.extend(argv);
+    wrapped
+}
+
+/// Lowers the priority of `argv` according to the `[nice]`, `[ionice]` and
+/// `[oom]` sections, for the first pattern matching any of `names`.
+/// `[nice]` values are a niceness, `[ionice]` values a class (`idle`,
+/// `best-effort` or `realtime`) optionally followed by `:level`, and
+/// `[oom]` values an OOM score adjustment (-1000 to 1000).
+pub fn priority(argv: Vec<String>, names: &[&str]) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    if let Some(nice) = CONFIG.lookup("nice", names) {
+        wrapped.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+    }
+    if let Some(ionice) = CONFIG.lookup("ionice", names) {
+        let (class, level) = match ionice.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (ionice, None),
+        };
+        let class = match class {
+            "realtime" => "1",
+            "best-effort" => "2",
+            "idle" => "3",
+            x => x,
+        };
+        wrapped.extend(["ionice".to_string(), "-c".to_string(), class.to_string()]);
+        if let Some(level) = level {
+            wrapped.extend(["-n".to_string(), level.to_string()]);
+        }
+    }
+    if let Some(oom) = CONFIG.lookup("oom", names) {
+        wrapped.extend([
+            "choom".to_string(),
+            "-n".to_string(),
+            oom.to_string(),
+            "--".to_string(),
+        ]);
+    }
+    wrapped.extend(argv);
+    wrapped
+}
+
+/// Shows a notification offering to relaunch `argv` when it fails within a
+/// few seconds, if any of `names` matches a pattern in the `[watch]`
+/// section. Values are how long to watch, e.g. `10s`, or `true` for 5
+/// seconds. The shell that waits for it is detached like any program.
+pub fn watch(argv: Vec<String>, names: &[&str]) -> Vec<String> {
+    let Some(duration) = CONFIG.lookup("watch", names) else {
+        return argv;
+    };
+    let seconds = match duration {
+        "true" => 5,
+        x => match crate::history::parse_duration(x) {
+            Some(x) => x,
+            None => {
+                eprintln!("dmenu_drun: invalid watch duration `{}`, using 5s", x);
+                5
+            }
+        },
+    };
+    let name = names.first().unwrap_or(&argv[0].as_str()).to_string();
+    let script = r#"seconds=$1 summary=$2 label=$3; shift 3
+start=$(date +%s)
+"$@" && exit
+[ $(($(date +%s) - start)) -lt "$seconds" ] || exit
+action=$(notify-send -a dmenu_drun -A relaunch="$label" "$summary")
+[ "$action" = relaunch ] && exec "$@""#;
+    let mut wrapped = vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        script.to_string(),
+        "sh".to_string(),
+        seconds.to_string(),
+        crate::tr!("{} quit unexpectedly", name),
+        crate::tr!("Relaunch"),
+    ];
+    wrapped.extend(argv);
+    wrapped
+}
+
+/// Applies all configured wrappers (GPU offloading, sandboxing, inhibitors,
+/// priorities and crash watching).
+pub fn wrap(argv: Vec<String>, names: &[&str], prefers_gpu: bool) -> Vec<String> {
+    let argv = sandbox(offload(argv, names, prefers_gpu), names);
+    watch(priority(inhibit(argv, names), names), names)
+}
+
+/// Builds the command opening `target` (a file or URL) with `entry`,
+/// wrapped like `desktop_commands`.
+pub fn open_command(entry: &DesktopEntry, target: &str, names: &[&str]) -> Option<Command> {
+    exec_command(entry, entry.open_argv(target)?, names, false).ok()
+}
+
+/// Builds the command running `argv` for `entry`, wrapped, and elevated
+/// when `root` or the desktop file asks for it, in its `Path`.
+fn exec_command(
+    entry: &DesktopEntry,
+    argv: Vec<String>,
+    names: &[&str],
+    root: bool,
+) -> Result<Command> {
+    let elevated = root || entry.get_bool("X-KDE-SubstituteUID");
+    let mut argv = wrap(argv, names, entry.get_bool("PrefersNonDefaultGPU"));
+    if elevated {
+        let user = entry.get("X-KDE-Username").filter(|_| !root);
+        argv = elevate(&argv, user);
+    }
+    let mut command = if entry.get_bool("Terminal") {
+        in_terminal(&argv)?
+    } else {
+        to_command(&argv)?
+    };
+    if let Some(dir) = entry.get("Path").filter(|x| !x.is_empty()) {
+        command.current_dir(dir);
+    }
+    Ok(command)
+}
+
+/// The commands to try for `entry`, until one starts (when a program is
+/// missing or not executable, it doesn't), in order: the `Exec` line
+/// itself, its program replaced by `TryExec`, or by its bare name looked up
+/// in `$PATH`, and finally gtk-launch. gtk-launch isn't tried for entries
+/// that are elevated or sandboxed, it would do neither.
+pub fn desktop_commands(entry: &DesktopEntry, names: &[&str], root: bool) -> Vec<Command> {
+    let elevated = root || entry.get_bool("X-KDE-SubstituteUID");
+    let mut commands = entry
+        .exec_argv()
+        .map(|x| exec_candidates(entry, x))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|x| exec_command(entry, x, names, root).ok())
+        .collect::<Vec<_>>();
+    let sandboxed = CONFIG.lookup("sandbox", names).is_some();
+    if !elevated && !sandboxed && which("gtk-launch").is_some() {
+        let mut command = Command::new("gtk-launch");
+        command.arg(entry.path.file_name().unwrap_or_default());
+        commands.push(command);
+    }
+    commands
+}
+
+/// `argv` and its variants for `desktop_commands`.
+fn exec_candidates(entry: &DesktopEntry, argv: Vec<String>) -> Vec<Vec<String>> {
+    // Skipping `env VAR=value`
+    let mut program = 0;
+    if argv[0] == "env" {
+        program = 1 + argv[1..].iter().take_while(|x| x.contains('=')).count();
+    }
+    if program >= argv.len() {
+        return Vec::new();
+    }
+    let with_program = |replacement: &str| {
+        let mut argv = argv.clone();
+        argv[program] = replacement.to_string();
+        argv
+    };
+    let mut candidates = vec![argv.clone()];
+    if let Some(try_exec) = entry.get("TryExec").filter(|x| *x != argv[program]) {
+        candidates.push(with_program(try_exec));
+    }
+    let binary = Path::new(&argv[program]).file_name().unwrap_or_default();
+    if argv[program].contains('/') && which(&binary.to_string_lossy()).is_some() {
+        candidates.push(with_program(&binary.to_string_lossy()));
+    }
+    candidates
+}
@@ -1,62 +1,1184 @@
-#![feature(option_result_contains)]
-#![feature(hash_drain_filter)]
-// This will only work on linux, we're using DMenu anyways.
-#![cfg(target_os = "linux")]
+// This will only work on unix, we're using DMenu anyways.
+#![cfg(unix)]
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::process::{Command, Stdio};
+use std::io::{BufRead, Read, Write};
+use std::process::Command;
 use std::str::FromStr;
 use std::{
     fs::{DirEntry, File},
     io::BufWriter,
     os::unix::prelude::PermissionsExt,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use fork::{daemon, Fork};
 use itertools::Itertools;
 
+use config::CONFIG;
+use desktop::DesktopEntry;
+
+mod audio;
+mod audit;
+mod bangs;
+mod bluetooth;
+mod bookmarks;
+mod clipboard;
+mod commands;
+mod config;
+mod convert;
+mod desktop;
+mod display;
+mod drives;
+mod files;
+mod focus;
+mod history;
+mod i18n;
+mod icons;
+mod launch;
+mod menu;
+mod mimeapps;
+mod net;
+mod notify;
+mod overrides;
+mod pass;
+mod places;
+mod remote;
+mod script;
+mod search;
+mod speech;
+mod stats;
+mod xdg_menu;
+
+#[cfg(test)]
+mod tests;
+
 lazy_static::lazy_static! {
     pub static ref DESKTOP_FOLDER: PathBuf = dirs::home_dir().unwrap().join("Desktop");
     pub static ref LOCAL_APPLICATIONS: PathBuf = dirs::data_local_dir().unwrap().join("applications");
-    pub static ref DESKTOP_DIRS: [PathBuf; 3] = [
-        DESKTOP_FOLDER.to_path_buf(),
-        PathBuf::from("/usr/share/applications"),
-        LOCAL_APPLICATIONS.to_path_buf(),
-    ];
+    /// Where desktop files are found, later directories take precedence.
+    pub static ref DESKTOP_DIRS: Vec<PathBuf> = desktop_dirs();
     pub static ref PATH: String = std::env::var("PATH").unwrap_or_default();
     pub static ref PATH_DIRS: Vec<PathBuf> = PATH
         .split(':')
-        .filter_map(|x| PathBuf::try_from(x).ok())
+        .map(PathBuf::from)
         .collect();
 }
 
+/// The directories in `desktop_dirs` in the `[general]` section, which
+/// lists the most important first. By default the user's applications
+/// shadow the system's in `$XDG_DATA_DIRS` (e.g. `/usr/local/share` on
+/// the BSDs), which shadow `~/Desktop`.
+fn desktop_dirs() -> Vec<PathBuf> {
+    match CONFIG.get("general", "desktop_dirs") {
+        Some(dirs) => dirs
+            .split_whitespace()
+            .rev()
+            .map(|x| PathBuf::from(expand_tilde(x)))
+            .collect(),
+        None => std::iter::once(DESKTOP_FOLDER.to_path_buf())
+            .chain(
+                config::data_dirs()
+                    .into_iter()
+                    .rev()
+                    .map(|x| x.join("applications")),
+            )
+            .collect(),
+    }
+}
+
+/// Options and subcommands shown by `--help`, with their descriptions.
+const HELP: &[(&str, &str)] = &[
+    ("-p", "hide files in $PATH"),
+    ("-d", "hide desktop files"),
+    (
+        "-g",
+        "hide files in $PATH that probably aren't GUI programs",
+    ),
+    (
+        "--dry-run",
+        "print the command line instead of launching it",
+    ),
+    ("--stdout", "print the selection instead of launching it"),
+    (
+        "--loop",
+        "show the menu again after launching, until nothing is selected",
+    ),
+    (
+        "--stdin",
+        "add the lines on stdin to the menu as shell commands",
+    ),
+    (
+        "-0, --null",
+        "separate stdin entries and printed lines with NUL",
+    ),
+    (
+        "--sort recent",
+        "show the most recently launched entries first",
+    ),
+    (
+        "--sort frecency",
+        "show the most launched entries first, favoring recent ones",
+    ),
+    (
+        "--profile <name>",
+        "use the config sections for this profile",
+    ),
+    (
+        "--select <query>",
+        "only show entries containing this, launch it if there's one",
+    ),
+    (
+        "--timeout <seconds>",
+        "close the menu after this long, see timeout_default",
+    ),
+    (
+        "--auto-select",
+        "launch as soon as one entry is left while typing (rofi)",
+    ),
+    (
+        "--no-cache",
+        "scan every time, without reading or writing the cache",
+    ),
+    (
+        "--kiosk",
+        "only show and launch the entries allowed in [kiosk]",
+    ),
+    (
+        "--verbose",
+        "print the files and directories skipped while scanning",
+    ),
+    (
+        "--cache-file <path>",
+        "keep the cache here, also $DMENU_DRUN_CACHE_FILE",
+    ),
+    (
+        "--history-file <path>",
+        "keep the history here, also $DMENU_DRUN_HISTORY_FILE",
+    ),
+    ("edit", "open the selected desktop file in $EDITOR"),
+    ("copy", "copy the command line instead of launching it"),
+    (
+        "create [<binary>]",
+        "create a desktop file for an executable",
+    ),
+    ("hide", "add the selection to the hidden entries"),
+    (
+        "tweak [<desktop-id>]",
+        "make a common change to a local copy of a desktop file",
+    ),
+    (
+        "restore [<desktop-id>]",
+        "delete the local copy of a desktop file shadowing another",
+    ),
+    (
+        "launch <id|name>",
+        "launch a desktop file id, executable or entry without a menu",
+    ),
+    ("man", "open the man page of the selected executable"),
+    (
+        "reveal",
+        "open the directory of the selected desktop file or executable",
+    ),
+    (
+        "categories",
+        "browse desktop files by the XDG menu's categories",
+    ),
+    (
+        "files [<query>]",
+        "open a file found with fd or locate, --open-with asks with what",
+    ),
+    (
+        "open <path|url>",
+        "open with the default application, like xdg-open",
+    ),
+    (
+        "default [<file|type>]",
+        "choose the application that opens a type of file",
+    ),
+    (
+        "jump",
+        "open a recent directory, from zoxide if it's installed",
+    ),
+    (
+        "terminal",
+        "open a terminal in a bookmarked, XDG user or recent directory",
+    ),
+    (
+        "remote [<host>]",
+        "launch an application on another machine over SSH",
+    ),
+    ("list", "print the menu entries"),
+    (
+        "warm",
+        "rebuild the cache if needed, e.g. from your autostart",
+    ),
+    (
+        "lint [<file>...]",
+        "check desktop files for mistakes, all of them by default",
+    ),
+    (
+        "stats [--json]",
+        "show the most used entries and how often you launch things",
+    ),
+    (
+        "doctor",
+        "check the menu and gtk-launch, and show skipped files",
+    ),
+    (
+        "cache stats",
+        "show where entries come from and what was filtered",
+    ),
+    (
+        "history clear",
+        "forget launched entries and learned abbreviations",
+    ),
+    (
+        "history merge <file>",
+        "add the history of another machine to this one",
+    ),
+    ("audio", "set the default audio output or input"),
+    ("bookmarks", "open a bookmark of Firefox or Chromium"),
+    (
+        "bluetooth",
+        "connect to or disconnect from a Bluetooth device",
+    ),
+    ("display", "switch to another layout of the screens"),
+    ("drives", "mount, open or unmount a removable drive"),
+    ("net", "connect to or disconnect from a network"),
+    ("pass", "copy or type a password from pass"),
+    (
+        "search [query]",
+        "search with the installed GNOME Shell search providers",
+    ),
+];
+
+/// Options that take a value as the next argument.
+const VALUE_OPTIONS: [&str; 6] = [
+    "--sort",
+    "--profile",
+    "--cache-file",
+    "--history-file",
+    "--select",
+    "--timeout",
+];
+
 type Result<T = ()> = core::result::Result<T, Box<dyn std::error::Error>>;
 
+/// Returned when a prompt is answered with "no", so we exit without
+/// printing an error.
+#[derive(Debug)]
+struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&tr!("Cancelled"))
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Returns the value passed to option `name`, e.g. `--sort recent`.
+pub fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|x| x == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// A path to keep state in, from option `name` (e.g. `--cache-file`) or else
+/// environment variable `var`.
+fn path_override(args: &[String], name: &str, var: &str) -> Option<PathBuf> {
+    option(args, name)
+        .map(ToString::to_string)
+        .or_else(|| std::env::var(var).ok().filter(|x| !x.is_empty()))
+        .map(|x| PathBuf::from(expand_tilde(&x)))
+}
+
+/// The options that change how things are done deep down, read from the
+/// arguments once and passed to what they change.
+#[derive(Clone, Debug, Default)]
+struct Flags {
+    /// The first argument that's not an option or an option's value
+    subcommand: Option<String>,
+    /// The ones after it, e.g. the file for `open`
+    operands: Vec<String>,
+    /// `--profile <name>`, see `config::set_profile`
+    profile: Option<String>,
+    /// Only the entries in `allow` of the `[kiosk]` section can be
+    /// launched, with `--kiosk` or `enabled = true` in that section
+    kiosk: bool,
+    /// `--no-cache`, nothing is read from or written to the cache files
+    no_cache: bool,
+    /// `--auto-select`, see `menu::Picker`
+    auto_select: bool,
+    /// `--timeout <seconds>`, see `menu::Picker`
+    timeout: Option<std::time::Duration>,
+    /// `--cache-file <path>` or `$DMENU_DRUN_CACHE_FILE`
+    cache_file: Option<PathBuf>,
+    /// `--history-file <path>` or `$DMENU_DRUN_HISTORY_FILE`
+    history_file: Option<PathBuf>,
+}
+
+impl Flags {
+    /// Reads `args`, without the program name. Doesn't read the config,
+    /// which depends on `--profile`: `enabled` in `[kiosk]` is added to
+    /// `kiosk` after.
+    fn parse(args: &[String]) -> Self {
+        let mut positionals = args
+            .iter()
+            .enumerate()
+            .filter(|(i, x)| {
+                !x.starts_with('-') && (*i == 0 || !VALUE_OPTIONS.contains(&args[i - 1].as_str()))
+            })
+            .map(|x| x.1.clone());
+        Self {
+            subcommand: positionals.next(),
+            operands: positionals.collect(),
+            profile: option(args, "--profile").map(ToString::to_string),
+            kiosk: args.contains(&"--kiosk".to_string()),
+            no_cache: args.contains(&"--no-cache".to_string()),
+            auto_select: args.contains(&"--auto-select".to_string()),
+            timeout: option(args, "--timeout")
+                .and_then(|x| x.parse::<f64>().ok())
+                .filter(|x| *x > 0.)
+                .map(std::time::Duration::from_secs_f64),
+            cache_file: path_override(args, "--cache-file", "DMENU_DRUN_CACHE_FILE"),
+            history_file: path_override(args, "--history-file", "DMENU_DRUN_HISTORY_FILE"),
+        }
+    }
+
+    /// Where the cache is kept, or `None` with `--no-cache`.
+    fn cache_path(&self) -> Option<PathBuf> {
+        if self.no_cache {
+            return None;
+        }
+        if let Some(path) = &self.cache_file {
+            return Some(path.clone());
+        }
+        let cache_dir = dirs::cache_dir().unwrap();
+        // Names are localized, so every set of locales gets its own cache
+        let mut name = match desktop::LOCALE_SUFFIXES.join(":") {
+            x if x.is_empty() => ".dmenu_rs_cache".to_string(),
+            x => format!(".dmenu_rs_cache.{}", x),
+        };
+        // As does every profile, its sections change what's scanned and filtered
+        if let Some(profile) = CONFIG.profile() {
+            name.push_str(&format!("+{}", profile));
+        }
+        // And every desktop, for `OnlyShowIn` and `NotShowIn`
+        let desktop = std::env::var("XDG_CURRENT_DESKTOP").ok();
+        if let Some(desktop) = desktop.filter(|x| !x.is_empty()) {
+            name.push_str(&format!("@{}", desktop));
+        }
+        Some(config::namespaced(cache_dir.join(name)))
+    }
+}
+
 fn main() -> Result {
+    match run() {
+        Err(e) if e.is::<Cancelled>() => std::process::exit(1),
+        x => x,
+    }
+}
+
+fn run() -> Result {
     let args = std::env::args().collect_vec();
+    let mut flags = Flags::parse(&args[1..]);
+    // Before anything reads the config
+    if let Some(profile) = &flags.profile {
+        config::set_profile(profile);
+    }
+    flags.kiosk |= CONFIG.get("kiosk", "enabled") == Some("true");
 
     if args.contains(&"--help".to_string()) {
-        println!("Usage: dmenu_drun [--help] [-d] [-p]");
-        println!("    -p        hide files in $PATH");
-        println!("    -d        hide desktop files");
+        println!(
+            "{}: dmenu_drun [--help] [--dry-run] [--stdout] [--loop] [--stdin] [-0] [-d] [-p] [-g] \
+             [--sort recent|frecency] [--profile <name>] [--select <query>] [--auto-select] [--timeout <seconds>] [--no-cache] [--kiosk] [--verbose] [--cache-file <path>] [--history-file <path>] [edit|copy|create [<binary>]|hide|tweak [<desktop-id>]|restore [<desktop-id>]|launch <id|name>|man|reveal|categories|files [<query>]|open <path|url>|audio|bookmarks|bluetooth|display|drives|net|pass|search [<query>]|default [<file|type>]|jump|terminal|remote [<host>]|list|stats [--json]|cache stats|doctor|warm|lint [<file>...]|history clear|history merge <file>]",
+            tr!("Usage")
+        );
+        let width = HELP.iter().map(|x| x.0.len()).max().unwrap_or_default() + 2;
+        for (option, help) in HELP {
+            println!(
+                "    {:<width$}{}",
+                option,
+                i18n::translate(help, &[]),
+                width = width
+            );
+        }
+        return Ok(());
+    }
+
+    let subcommand = flags.subcommand.clone();
+    if let Some(subcommand) = subcommand.as_ref().filter(|_| flags.kiosk) {
+        return Err(tr!("{} isn't available in kiosk mode", subcommand).into());
+    }
+
+    // These don't need the cache or a menu
+    match subcommand.as_deref() {
+        Some("history") => return commands::history(&flags),
+        Some("lint") => return commands::lint(&flags),
+        Some("warm") => return commands::warm(&flags),
+        _ => {}
+    }
+
+    let s = &mut commands::Session::load(args, flags)?;
+    match subcommand.as_deref() {
+        Some("categories") => commands::categories(s),
+        Some("edit") => commands::edit(s),
+        Some("tweak") => commands::tweak(s),
+        Some("restore") => commands::restore(s),
+        Some("create") => commands::create(s),
+        Some("hide") => commands::hide(s),
+        Some("man") => commands::man(s),
+        Some("reveal") => commands::reveal(s),
+        Some("copy") => commands::copy(s),
+        Some(x @ ("jump" | "terminal")) => commands::jump(s, x),
+        Some("remote") => commands::remote(s),
+        Some("files") => commands::files(s),
+        Some("open") => commands::open(s),
+        Some("bookmarks") => commands::bookmarks(s),
+        Some("audio") => commands::audio(s),
+        Some("bluetooth") => commands::bluetooth(s),
+        Some("display") => commands::display(s),
+        Some("drives") => commands::drives(s),
+        Some("net") => commands::net(s),
+        Some("pass") => commands::pass(s),
+        Some("search") => commands::search(s),
+        Some("default") => commands::default(s),
+        Some("cache") => commands::cache(s),
+        Some("doctor") => commands::doctor(s),
+        Some("stats") => commands::stats(s),
+        Some("launch") => commands::launch(s),
+        Some("list") => commands::list(s),
+        _ => commands::menu(s),
+    }
+}
+
+/// Narrows the menu down to the entries whose name or file name contains
+/// `query`, ignoring case, if there are any. Returns the entry to launch
+/// without a menu: the only one left, or the only one named `query`.
+fn preselect(cache: &mut Cache, query: &str) -> Option<String> {
+    let query = query.to_lowercase();
+    let names = cache
+        .entries
+        .iter()
+        .filter(|(k, v)| k.to_lowercase().contains(&query) || v.to_lowercase().contains(&query))
+        .map(|x| x.0.clone())
+        .chain(
+            cache
+                .commands
+                .keys()
+                .filter(|k| k.to_lowercase().contains(&query))
+                .cloned(),
+        )
+        .unique()
+        .collect_vec();
+    if names.is_empty() {
+        return None;
+    }
+    cache.entries.retain(|k, _| names.contains(k));
+    cache.commands.retain(|k, _| names.contains(k));
+    cache
+        .aliases
+        .retain(|_, v| cache.entries.values().any(|x| x == v));
+    let exact = names
+        .iter()
+        .filter(|x| {
+            x.to_lowercase() == query
+                || cache
+                    .entries
+                    .get(*x)
+                    .is_some_and(|x| x.trim_end_matches(".desktop").to_lowercase() == query)
+        })
+        .collect_vec();
+    if let [name] = exact[..] {
+        return Some(name.clone());
+    }
+    (names.len() == 1).then(|| names[0].clone())
+}
+
+/// Applies the filters from the arguments and the `[filter]` section.
+/// Returns the ones that are active, with the amount of entries they
+/// removed.
+fn apply_filters(cache: &mut Cache, args: &[String], flags: &Flags) -> Vec<(&'static str, usize)> {
+    let mut filtered = Vec::new();
+    let mut filter = |name, cache: &mut Cache, f: &mut dyn FnMut(&mut Cache)| {
+        let before = cache.entries.len();
+        f(cache);
+        filtered.push((name, before - cache.entries.len()));
+    };
+
+    if args.contains(&"-p".to_string()) {
+        // Commands from scripts and stdin aren't in `$PATH`, they stay
+        filter("apps", cache, &mut |cache| {
+            cache.entries.retain(|_, v| v.ends_with(".desktop"));
+            cache.aliases.retain(|_, v| v.ends_with(".desktop"));
+        });
+    }
+
+    if args.contains(&"-d".to_string()) {
+        filter("run", cache, &mut |cache| {
+            cache.retain(|_, v| !v.ends_with(".desktop"))
+        });
+    }
+
+    if args.contains(&"-g".to_string()) || CONFIG.get("filter", "gui_only") == Some("true") {
+        filter("gui", cache, &mut gui_only);
+    }
+
+    if let Some(hide) = CONFIG.get("filter", "hide") {
+        let patterns = hide.split_whitespace().collect_vec();
+        filter("hide", cache, &mut |cache| {
+            cache.retain(|k, v| {
+                !patterns
+                    .iter()
+                    .any(|x| config::glob_match(x, k) || config::glob_match(x, v))
+            })
+        });
+    }
+
+    if CONFIG.get("filter", "check_exec") == Some("true") {
+        filter("exec", cache, &mut |cache| {
+            let mut execs = launch::ExecCache::load(flags.cache_path());
+            let missing = cache
+                .execs
+                .iter()
+                .filter(|(_, program)| !execs.exists(program))
+                .map(|(file_name, _)| file_name.clone())
+                .collect::<std::collections::HashSet<_>>();
+            if let Err(e) = execs.save() {
+                eprintln!("dmenu_drun: could not save the checked programs: {}", e);
+            }
+            cache.retain(|_, v| !missing.contains(v));
+        });
+    }
+
+    if CONFIG.get("filter", "xdg_menu") == Some("true") {
+        filter("menu", cache, &mut |cache| {
+            let in_menu = load_xdg_menu(cache)
+                .map(|x| x.all_entries())
+                .unwrap_or_default();
+            cache.retain(|_, v| !v.ends_with(".desktop") || in_menu.contains(v));
+        });
+    }
+
+    if flags.kiosk {
+        let allow = CONFIG.get("kiosk", "allow").unwrap_or_default();
+        let patterns = allow.split_whitespace().collect_vec();
+        filter("kiosk", cache, &mut |cache| {
+            cache.retain(|_, v| patterns.iter().any(|x| config::glob_match(x, v)))
+        });
+    }
+
+    filtered
+}
+
+/// Extra entries piped in by a script, separated by `delimiter`. Entries
+/// are either a shell command, or a name and a command separated by a tab.
+fn stdin_entries(delimiter: u8) -> Vec<(String, String)> {
+    std::io::stdin()
+        .lock()
+        .split(delimiter)
+        .map_while(std::result::Result::ok)
+        .map(|x| String::from_utf8_lossy(&x).to_string())
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| match x.split_once('\t') {
+            Some((name, command)) => (name.to_string(), command.to_string()),
+            None => (x.clone(), x),
+        })
+        .collect()
+}
+
+/// Sorts the cached desktop files into the XDG menu.
+fn load_xdg_menu(cache: &Cache) -> Option<xdg_menu::Menu> {
+    let apps = cache
+        .entries
+        .values()
+        .filter(|x| x.ends_with(".desktop"))
+        .filter_map(|x| Some(xdg_menu::App::new(x, &DesktopEntry::find(x)?)))
+        .collect_vec();
+    xdg_menu::Menu::load(&apps)
+}
+
+/// Shows the XDG menu one level at a time, submenus end with a `/`.
+fn browse_categories(
+    cache: &Cache,
+    history: &mut history::History,
+    scripts: &script::Scripts,
+    backend: &dyn menu::Menu,
+    spawner: &mut dyn launch::Spawner,
+    flags: &Flags,
+) -> Result {
+    let root = load_xdg_menu(cache).ok_or_else(|| tr!("No applications.menu found"))?;
+    let names: HashMap<&String, &String> = cache.entries.iter().map(|(k, v)| (v, k)).collect();
+    let mut path = vec![&root];
+    while let Some(menu) = path.last() {
+        let submenus = menu.submenus.iter().filter(|x| !x.is_empty()).collect_vec();
+        let lines = (path.len() > 1)
+            .then(|| "..".to_string())
+            .into_iter()
+            .chain(submenus.iter().map(|x| format!("{}/", x.title())))
+            .chain(
+                menu.entries
+                    .iter()
+                    .filter_map(|x| names.get(x).map(|x| x.to_string()))
+                    .sorted(),
+            )
+            .collect_vec();
+        let output = backend.choose(&menu.title(), &lines)?;
+        if output.is_empty() {
+            std::process::exit(1);
+        } else if output == ".." {
+            path.pop();
+        } else if let Some(submenu) = submenus
+            .iter()
+            .find(|x| format!("{}/", x.title()) == output)
+        {
+            path.push(submenu);
+        } else {
+            return launch_selection(
+                cache,
+                history,
+                scripts,
+                backend,
+                spawner,
+                flags,
+                "categories",
+                &output,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Launches what was selected in (or typed into) the menu. `mode` is
+/// recorded in the audit log.
+#[allow(clippy::too_many_arguments)]
+fn launch_selection(
+    cache: &Cache,
+    history: &mut history::History,
+    scripts: &script::Scripts,
+    backend: &dyn menu::Menu,
+    spawner: &mut dyn launch::Spawner,
+    flags: &Flags,
+    mode: &str,
+    output: &str,
+) -> Result {
+    // Nothing typed runs in kiosk mode, not even with arguments
+    if flags.kiosk && resolve(cache, output).is_none() && !cache.commands.contains_key(output) {
+        return Err(tr!("Only the entries in the menu can be launched in kiosk mode").into());
+    }
+    // `!w dmenu` searches Wikipedia for dmenu
+    if let Some(url) = bangs::url(output) {
+        let mut command = Command::new("xdg-open");
+        command.arg(url);
+        launch::pass_activation_token(&mut command);
+        return spawn_audited(spawner, mode, output, None, &mut command);
+    }
+    // `=5km in mi` converts units, the result is copied when it's selected
+    if let Some(expr) = output.strip_prefix('=') {
+        let (value, unit) =
+            convert::convert(expr).ok_or_else(|| tr!("Could not convert {}", expr.trim()))?;
+        let line = format!("{} {}", convert::format(value), unit);
+        if backend.choose(expr.trim(), std::slice::from_ref(&line))? == line {
+            clipboard::copy(&convert::format(value))?;
+        }
+        return Ok(());
+    }
+    // `timer 10m tea` shows a notification saying "tea" in 10 minutes,
+    // unless there's something called like that
+    if let ["timer", duration, message @ ..] = &output.split_whitespace().collect_vec()[..] {
+        if let Some(seconds) =
+            history::parse_duration(duration).filter(|_| resolve(cache, output).is_none())
+        {
+            let message = Some(message.join(" ")).filter(|x| !x.is_empty());
+            let mut command = notify::timer(seconds, message.as_deref());
+            return spawn_audited(spawner, mode, output, None, &mut command);
+        }
+    }
+    // Typing `pin_prefix` (default `+`) before an entry pins it to the top
+    // of the menu, or unpins it
+    let pin_prefix = CONFIG.get("general", "pin_prefix").unwrap_or("+");
+    if let Some(name) = output
+        .strip_prefix(pin_prefix)
+        .filter(|_| !pin_prefix.is_empty())
+        .map(str::trim_start)
+    {
+        let entry = resolve(cache, name)
+            .map(String::as_str)
+            .or_else(|| cache.commands.contains_key(name).then_some(name))
+            .ok_or_else(|| tr!("Nothing called {} to pin", name))?;
+        let message = if history.toggle_pin(entry) {
+            tr!("Pinned {}", name)
+        } else {
+            tr!("Unpinned {}", name)
+        };
+        history.save()?;
+        eprintln!("dmenu_drun: {}", message);
+        return Ok(());
+    }
+    let (output, root) = strip_root_prefix(output);
+    if root && output.is_empty() {
+        return Err(tr!("Nothing to run as root").into());
+    }
+    let (entry, mut args) = match resolve(cache, output) {
+        Some(entry) => (Some(entry), Vec::new()),
+        None => resolve_with_args(cache, output),
+    };
+    let value = entry
+        .or_else(|| cache.commands.get(output))
+        .map(String::as_str);
+    // Anything but an entry picked as is, e.g. `sh -c '...'` when `sh` is
+    // in the menu, is run as typed
+    let raw = (entry.is_none() || !args.is_empty()) && !cache.commands.contains_key(output);
+    if !scripts.allow(output, value.unwrap_or(output)) {
+        return Err(tr!("Launching {} was refused by a script", output).into());
+    }
+    // Entries in `[focus]` raise a window they already have instead of
+    // starting again
+    if let Some(entry) = entry.filter(|x| {
+        args.is_empty()
+            && !root
+            && !spawner.is_dry_run()
+            && CONFIG.lookup("focus", &[output, x]) == Some("true")
+    }) {
+        if window_class(entry).is_some_and(|x| focus::focus(&x)) {
+            history.learn(entry);
+            return history.save();
+        }
+    }
+    // Executables in `[ask_args]` get a second menu for their arguments,
+    // listing the ones they were launched with before, except in kiosk mode
+    let executable = entry
+        .filter(|x| !x.ends_with(".desktop") && !cache.commands.contains_key(output) && !flags.kiosk);
+    let mut typed_args = None;
+    if let Some(entry) = executable.filter(|_| args.is_empty()) {
+        if CONFIG.lookup("ask_args", &[output, entry]) == Some("true") {
+            let previous = history.args.get(entry).cloned().unwrap_or_default();
+            let line = backend.choose(&tr!("Arguments for {}:", output), &previous)?;
+            args = shell_words::split(&line)?
+                .iter()
+                .map(|x| expand_tilde(x))
+                .collect();
+            typed_args = Some(line).filter(|x| !x.is_empty());
+        }
+    }
+    if let Some(prompt) = CONFIG.lookup("confirm", &[output, value.unwrap_or(output)]) {
+        let prompt = match prompt {
+            "true" => tr!("Launch {}?", output),
+            x => x.to_string(),
+        };
+        if !backend.confirm(&prompt)? {
+            return Err(Cancelled.into());
+        }
+    }
+    if raw {
+        check_raw_command(cache, output, backend)?;
+    }
+    let (mut command, fallbacks) = selection_commands(cache, output, entry, args, root)?;
+    launch::pass_activation_token(&mut command);
+    let mut launched = spawn_audited(
+        spawner,
+        mode,
+        output,
+        entry.map(String::as_str),
+        &mut command,
+    );
+    // Desktop files with a broken `Exec` may still start another way
+    for mut fallback in fallbacks {
+        if launched.is_ok() {
+            break;
+        }
+        launch::pass_activation_token(&mut fallback);
+        let retried = spawn_audited(
+            spawner,
+            mode,
+            output,
+            entry.map(String::as_str),
+            &mut fallback,
+        );
+        if retried.is_ok() {
+            launched = retried;
+        }
+    }
+    // A dry run only prints what would run, it's not remembered or announced
+    if spawner.is_dry_run() {
+        return launched;
+    }
+    speech::say(&match launched {
+        Ok(_) => tr!("Launched {}", output),
+        Err(_) => tr!("Could not launch {}", output),
+    });
+    match (entry, &launched) {
+        (Some(entry), Ok(_)) => {
+            let icon = DesktopEntry::find(entry).filter(|_| entry.ends_with(".desktop"));
+            notify::starting(&[output, entry], icon.as_ref().and_then(|x| x.get("Icon")));
+            history.learn(entry);
+            if let Some(args) = &typed_args {
+                history.learn_args(entry, args);
+            }
+        }
+        // Maybe an abbreviation, learn it from what's picked next
+        (None, Err(_)) => history.set_pending(output),
+        _ => {}
+    }
+    history.save()?;
+    launched
+}
+
+/// Splits `root_prefix` (default `#`) off `output`. Typed before an entry,
+/// it runs the entry as root.
+fn strip_root_prefix(output: &str) -> (&str, bool) {
+    let root_prefix = CONFIG.get("general", "root_prefix").unwrap_or("#");
+    match output.strip_prefix(root_prefix) {
+        Some(x) if !root_prefix.is_empty() => (x.trim_start(), true),
+        _ => (output, false),
+    }
+}
+
+/// The command `launch_selection` runs for `output`, resolved to `entry`
+/// with `args`, and the ones to try when it doesn't start.
+fn selection_commands(
+    cache: &Cache,
+    output: &str,
+    entry: Option<&String>,
+    args: Vec<String>,
+    root: bool,
+) -> Result<(Command, Vec<Command>)> {
+    let to_command = |argv: Vec<String>| {
+        if root {
+            launch::to_command(&launch::elevate(&argv, None))
+        } else {
+            launch::to_command(&argv)
+        }
+    };
+    let mut fallbacks = Vec::new();
+    let command = match entry {
+        _ if cache.commands.contains_key(output) => to_command(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            cache.commands[output].clone(),
+        ])?,
+        Some(entry) if !entry.ends_with(".desktop") => {
+            let mut argv = vec![entry.clone()];
+            argv.extend(args);
+            to_command(launch::wrap(argv, &[entry], false))?
+        }
+        // The `Exec` line is run by us, gtk-launch is the last resort
+        Some(entry) => {
+            let commands = match DesktopEntry::find(entry) {
+                Some(x) => launch::desktop_commands(&x, &[output, entry], root),
+                None if root => Vec::new(),
+                None => {
+                    let mut command = Command::new("gtk-launch");
+                    command.arg(entry);
+                    vec![command]
+                }
+            };
+            let mut commands = commands.into_iter();
+            let command = commands
+                .next()
+                .ok_or_else(|| tr!("Could not read desktop file"))?;
+            fallbacks = commands.collect();
+            command
+        }
+        // Split like a shell would, so quoted arguments stay together
+        None => to_command(
+            shell_words::split(output)
+                .map_err(|e| tr!("Could not parse {}: {}", output, e))?
+                .iter()
+                .map(|x| expand_tilde(x))
+                .collect(),
+        )?,
+    };
+    Ok((command, fallbacks))
+}
+
+/// The class of `entry`'s windows: the desktop file's `StartupWMClass`, or
+/// else the name of the binary it runs.
+fn window_class(entry: &str) -> Option<String> {
+    if !entry.ends_with(".desktop") {
+        return Some(Path::new(entry).file_name()?.to_string_lossy().to_string());
+    }
+    let desktop_entry = DesktopEntry::find(entry)?;
+    desktop_entry
+        .get("StartupWMClass")
+        .map(ToString::to_string)
+        .or_else(|| desktop_entry.get("Exec").and_then(desktop::exec_binary))
+}
+
+/// The desktop files in the cache as (name, file name, MIME types), sorted
+/// by name.
+fn desktop_apps(cache: &Cache) -> Vec<(&String, &String, Vec<String>)> {
+    // Handlers are often kept out of the menu, not out of `Open with`
+    let handlers = cache
+        .hidden
+        .iter()
+        .filter(|x| x.2 == "NoDisplay")
+        .map(|x| (&x.0, &x.1));
+    cache
+        .entries
+        .iter()
+        .filter(|x| x.1.ends_with(".desktop"))
+        .chain(handlers)
+        .filter_map(|(name, file)| {
+            let types = DesktopEntry::find(file)?
+                .get("MimeType")
+                .unwrap_or_default()
+                .split(';')
+                .filter(|x| !x.is_empty())
+                .map(ToString::to_string)
+                .collect_vec();
+            Some((name, file, types))
+        })
+        .sorted()
+        .collect()
+}
+
+/// Orders `apps` (see `desktop_apps`) so the ones supporting `mime` come
+/// first.
+fn supporting_first<'a, T>(
+    apps: &'a [(T, T, Vec<String>)],
+    mime: &str,
+) -> Vec<&'a (T, T, Vec<String>)> {
+    let (supported, others): (Vec<_>, Vec<_>) =
+        apps.iter().partition(|x| x.2.iter().any(|x| x == mime));
+    supported.into_iter().chain(others).collect()
+}
+
+/// Asks which application to open `file` with, the ones supporting its
+/// type first. The first line opens it with the default application.
+fn open_with(cache: &Cache, backend: &dyn menu::Menu, file: &Path) -> Result<Command> {
+    let mime = mimeapps::filetype(file).unwrap_or_default();
+    let apps = desktop_apps(cache);
+    let apps = supporting_first(&apps, &mime);
+    let default = tr!("Default application");
+    let lines = std::iter::once(default.clone())
+        .chain(apps.iter().map(|x| x.0.clone()))
+        .collect_vec();
+    let prompt = tr!("Open {} with:", places::display(file));
+    let output = backend.choose(&prompt, &lines)?;
+    if output == default {
+        return Ok(places::xdg_open(file));
+    }
+    let Some((_, app, _)) = apps.iter().find(|x| *x.0 == output) else {
+        std::process::exit(1);
+    };
+    let mut command = Command::new("gtk-launch");
+    command.arg(app).arg(file);
+    Ok(command)
+}
+
+/// The command opening `target`, a path or URL, with the default application
+/// for its type in `mimeapps.list`, like xdg-open. Asks which application
+/// to use when there's no default, the ones supporting the type first.
+fn open(cache: &Cache, backend: &dyn menu::Menu, target: &str) -> Result<Command> {
+    let target = match target.strip_prefix("file://") {
+        Some(x) => places::percent_decode(x),
+        None => expand_tilde(target),
+    };
+    // `https://...` or `mailto:...`, unless it's a file called like that
+    let scheme = target
+        .split_once(':')
+        .map(|x| x.0)
+        .filter(|x| {
+            x.len() > 1
+                && x.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        })
+        .filter(|_| !Path::new(&target).exists());
+    let mime = match scheme {
+        Some(scheme) => format!("x-scheme-handler/{}", scheme.to_lowercase()),
+        None => mimeapps::filetype(Path::new(&target))
+            .ok_or_else(|| tr!("Could not find the MIME type of {}", target))?,
+    };
+    let app = match mimeapps::default_for(&mime).filter(|x| DesktopEntry::find(x).is_some()) {
+        Some(app) => app,
+        None => {
+            let apps = desktop_apps(cache);
+            let apps = supporting_first(&apps, &mime);
+            let names = apps.iter().map(|x| x.0.clone()).collect_vec();
+            let output = backend.choose(&tr!("Open {} with:", target), &names)?;
+            let Some((_, app, _)) = apps.iter().find(|x| *x.0 == output) else {
+                std::process::exit(1);
+            };
+            app.to_string()
+        }
+    };
+    let entry = DesktopEntry::find(&app).ok_or_else(|| tr!("Could not read desktop file"))?;
+    let name = entry.get_localized("Name").unwrap_or_default().to_string();
+    launch::open_command(&entry, &target, &[&name, &app])
+        .ok_or_else(|| tr!("{} has no Exec", app).into())
+}
+
+/// Asks which application should open a MIME type by default, and writes it
+/// to `mimeapps.list`. `target` is the type or a file to get it from, and
+/// is picked from the types desktop files support if `None`. Applications
+/// that support the type are listed first.
+fn set_default_app(cache: &Cache, backend: &dyn menu::Menu, target: Option<&str>) -> Result {
+    let apps = desktop_apps(cache);
+    let mime = match target {
+        Some(x) if Path::new(x).exists() => mimeapps::filetype(Path::new(x))
+            .ok_or_else(|| tr!("Could not find the MIME type of {}", x))?,
+        Some(x) => x.to_string(),
+        None => {
+            let types = apps.iter().flat_map(|x| x.2.clone()).sorted().dedup();
+            backend.choose(&tr!("MIME type"), &types.collect_vec())?
+        }
+    };
+    if mime.is_empty() {
+        std::process::exit(1);
+    }
+    let apps = supporting_first(&apps, &mime);
+    let prompt =
+        match mimeapps::default_for(&mime).and_then(|x| cache.entries.iter().find(|y| *y.1 == x)) {
+            Some((name, _)) => tr!("Open {} with (now {}):", mime, name),
+            None => tr!("Open {} with:", mime),
+        };
+    let names = apps.iter().map(|x| x.0.clone()).collect_vec();
+    let output = backend.choose(&prompt, &names)?;
+    let Some((_, file, _)) = apps.iter().find(|x| *x.0 == output) else {
+        std::process::exit(1);
+    };
+    mimeapps::set_default(&mime, file)
+}
+
+/// Applies `raw_commands` in the `[general]` section to input that's run
+/// as typed: `allow` (the default), `confirm` to ask first, or `allowlist`
+/// to only run the programs in `raw_allowlist`. Input that doesn't start
+/// with an installed program (or one in the menu) fails to launch anyway,
+/// and is left alone so it can still be learned as an abbreviation.
+fn check_raw_command(cache: &Cache, output: &str, backend: &dyn menu::Menu) -> Result {
+    let words = shell_words::split(output).unwrap_or_default();
+    let program = words.first().map(String::as_str).unwrap_or_default();
+    let installed = if resolve(cache, program).is_some() {
+        true
+    } else if program.contains('/') {
+        Path::new(program).is_file()
+    } else {
+        launch::which(program).is_some()
+    };
+    if !installed {
+        return Ok(());
+    }
+    match CONFIG.get("general", "raw_commands") {
+        None | Some("allow") => Ok(()),
+        Some("confirm") => {
+            if !backend.confirm(&tr!("Run {}?", output))? {
+                return Err(Cancelled.into());
+            }
+            Ok(())
+        }
+        Some("allowlist") => {
+            let name = Path::new(program)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let allowed = CONFIG
+                .get("general", "raw_allowlist")
+                .unwrap_or_default()
+                .split_whitespace()
+                .any(|x| x == name);
+            if allowed {
+                Ok(())
+            } else {
+                Err(tr!("Running {} as typed isn't allowed", program).into())
+            }
+        }
+        Some(x) => Err(tr!("Unknown raw_commands policy `{}`", x).into()),
+    }
+}
+
+/// Starts `command` and records it in the audit log, see `audit::record`.
+fn spawn_audited(
+    spawner: &mut dyn launch::Spawner,
+    mode: &str,
+    query: &str,
+    entry: Option<&str>,
+    command: &mut Command,
+) -> Result {
+    spawner.spawn(command)?;
+    if !spawner.is_dry_run() {
+        audit::record(mode, query, entry, command);
+    }
+    Ok(())
+}
+
+/// Resolves input like `mpv ~/video.mkv`: an executable in `$PATH`
+/// followed by arguments, split like a shell would.
+fn resolve_with_args<'a>(cache: &'a Cache, output: &str) -> (Option<&'a String>, Vec<String>) {
+    let Ok(words) = shell_words::split(output) else {
+        return (None, Vec::new());
+    };
+    match words.split_first() {
+        Some((first, args)) if !args.is_empty() => {
+            match resolve(cache, first).filter(|x| !x.ends_with(".desktop")) {
+                Some(entry) => (Some(entry), args.iter().map(|x| expand_tilde(x)).collect()),
+                None => (None, Vec::new()),
+            }
+        }
+        _ => (None, Vec::new()),
+    }
+}
+
+/// Expands a leading `~` to the home directory.
+fn expand_tilde(arg: &str) -> String {
+    match arg.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", dirs::home_dir().unwrap_or_default().display(), rest)
+        }
+        _ => arg.to_string(),
+    }
+}
+
+/// Rebuilds the cache right away, so the next menu shows a change made to
+/// the desktop files.
+fn rebuild_cache(flags: &Flags) -> Result {
+    let Some(cache_path) = flags.cache_path() else {
         return Ok(());
+    };
+    match std::fs::remove_file(cache_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
     }
+    load_cache(flags)?;
+    Ok(())
+}
 
-    let cache_dir = dirs::cache_dir().unwrap();
-    std::fs::create_dir_all(&cache_dir)?;
-    let cache_path = cache_dir.join(".dmenu_rs_cache");
+/// Reads the cache, rebuilding it first if any of the scanned directories
+/// changed since it was written.
+fn load_cache(flags: &Flags) -> Result<Cache> {
+    let Some(cache_path) = flags.cache_path() else {
+        let mut cache = create_path_cache()?;
+        cache.extend(create_desktop_cache()?);
+        return Ok(cache);
+    };
+    if let Some(cache_dir) = cache_path.parent() {
+        std::fs::create_dir_all(cache_dir)?;
+    }
 
     let cache_mtime = cache_path
         .metadata()
         .map_or_else(|_| std::time::UNIX_EPOCH, |x| x.modified().unwrap());
 
+    // The config changes what's scanned, so it invalidates the cache too.
+    // Its directory changes when it's removed or replaced.
+    let config_paths = [
+        config::CONFIG_PATH.to_path_buf(),
+        config::CONFIG_PATH
+            .parent()
+            .unwrap_or(Path::new("/"))
+            .to_path_buf(),
+    ];
     let rebuild_cache = !cache_path.exists()
-        || PATH_DIRS.iter().chain(DESKTOP_DIRS.iter()).any(|x| {
-            x.metadata()
-                .map(|x| x.modified().unwrap() > cache_mtime)
-                .unwrap_or(false)
-        });
+        || PATH_DIRS
+            .iter()
+            .chain(DESKTOP_DIRS.iter())
+            .chain(config_paths.iter())
+            .any(|x| {
+                x.metadata()
+                    .map(|x| x.modified().unwrap() > cache_mtime)
+                    .unwrap_or(false)
+            });
 
     let mut cache_file = File::options()
         .read(true)
@@ -66,87 +1188,518 @@ fn main() -> Result {
         .or_else(|_| File::create(&cache_path))
         .expect("Could not create cache file");
 
-    let mut cache = if rebuild_cache {
-        let mut cache = create_path_cache(&cache_file)?.0;
-        cache.extend(create_desktop_cache(&cache_file)?.0);
+    Ok(if rebuild_cache {
+        let mut cache = create_path_cache()?;
+        cache.extend(create_desktop_cache()?);
+        cache_file.set_len(0)?;
+        write!(BufWriter::new(&cache_file), "{}", cache)?;
         cache
     } else {
         let mut cache_str = String::new();
         cache_file.read_to_string(&mut cache_str)?;
-        Cache::from_str(&cache_str)?.0
-    };
+        Cache::from_str(&cache_str)?
+    })
+}
 
-    if args.contains(&"-p".to_string()) {
-        cache = cache.drain_filter(|k, v| k != v).collect();
+/// Shows the cached entries in the menu, returns the selection. `{count}`
+/// in `prompt` is replaced with the amount of entries. Lines are formatted
+/// with `format` in the `[menu]` section, where `{name}` is the entry,
+/// `{comment}` the desktop file's comment, `{source}` where it comes from
+/// (`app`, `bin` or `script`), `{override}` a `*` for desktop files with a
+/// local copy shadowing another, `{last_used}` when it was last launched, e.g.
+/// `2d ago`, `{count}` how often and `{rank}` its place when sorted by
+/// frecency. With markup, the values are styled and the rest of `format` is
+/// markup itself.
+fn show_menu(
+    cache: &Cache,
+    history: &history::History,
+    scripts: &script::Scripts,
+    backend: &dyn menu::Menu,
+    prompt: Option<&str>,
+    sort: Option<history::Sort>,
+) -> Result<String> {
+    let mut aliases: HashMap<&String, Vec<&String>> = HashMap::new();
+    for (alias, v) in &cache.aliases {
+        aliases.entry(v).or_default().push(alias);
     }
 
-    if args.contains(&"-d".to_string()) {
-        cache = cache
-            .drain_filter(|_, v| !v.ends_with(".desktop"))
-            .collect();
+    let names = menu_names(cache, history, sort);
+    let ranks: HashMap<&String, usize> = history
+        .used
+        .keys()
+        .map(|x| (history.rank(x, history::Sort::Frecency), x))
+        .sorted_by(|a, b| b.0.total_cmp(&a.0))
+        .map(|x| x.1)
+        .enumerate()
+        .map(|(i, x)| (x, i + 1))
+        .collect();
+    let markup = backend.markup();
+    let shadowing = match CONFIG.get("menu", "format") {
+        Some(x) if x.contains("{override}") => overrides::shadowing(),
+        _ => Vec::new(),
+    };
+    let source_color = CONFIG.get("menu", "source_color").unwrap_or("steelblue");
+    let source_style = format!("foreground=\"{}\"", source_color);
+    // The plain line, and the one that's shown
+    let format = |name: &String| match CONFIG.get("menu", "format") {
+        Some(format) => {
+            let entry = cache.entries.get(name);
+            let comment = entry
+                .and_then(|x| cache.comments.get(x))
+                .cloned()
+                .unwrap_or_default();
+            let overridden = match entry {
+                Some(x) if shadowing.contains(x) => "*",
+                _ => "",
+            };
+            let source = match entry {
+                Some(x) if x.ends_with(".desktop") => "app",
+                Some(_) => "bin",
+                None => "script",
+            };
+            let last_used = entry
+                .and_then(|x| history.used.get(x))
+                .map(|x| history::ago(*x))
+                .unwrap_or_default();
+            let count = entry
+                .and_then(|x| history.launches.get(x))
+                .copied()
+                .unwrap_or_default();
+            let rank = entry
+                .and_then(|x| ranks.get(x))
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            let fields = [
+                ("{name}", name.clone(), "weight=\"bold\""),
+                ("{comment}", comment, "alpha=\"60%\""),
+                ("{source}", source.to_string(), source_style.as_str()),
+                ("{override}", overridden.to_string(), ""),
+                ("{last_used}", last_used, ""),
+                ("{count}", count.to_string(), ""),
+                ("{rank}", rank, ""),
+            ];
+            let plain = fields
+                .iter()
+                .fold(format.to_string(), |line, (k, v, _)| line.replace(k, v));
+            let shown = if markup {
+                fields
+                    .iter()
+                    .fold(format.to_string(), |line, (k, v, style)| {
+                        line.replace(k, &menu::span(v, style))
+                    })
+            } else {
+                plain.clone()
+            };
+            (plain.trim().to_string(), shown.trim().to_string())
+        }
+        None if markup => (name.clone(), menu::span(name, "weight=\"bold\"")),
+        None => (name.clone(), name.clone()),
+    };
+    // Formatting and scripts change how lines look, map them back to the
+    // names by the plain text
+    let mut lines = names
+        .iter()
+        .map(|k| {
+            let (plain, shown) = format(k);
+            (scripts.display(&plain), scripts.display(&shown), *k)
+        })
+        .collect_vec();
+    // Lines that look the same get what they launch appended, instead of
+    // all but one being unreachable
+    let counts = lines.iter().map(|x| x.0.clone()).counts();
+    for (plain, shown, k) in &mut lines {
+        if counts[plain.as_str()] > 1 {
+            let id = cache
+                .entries
+                .get(*k)
+                .map_or(k.as_str(), |x| x.trim_end_matches(".desktop"));
+            let suffix = format!(" ({})", id);
+            shown.push_str(&if markup { menu::span(&suffix, "") } else { suffix.clone() });
+            plain.push_str(&suffix);
+        }
     }
+    let lines = lines.into_iter().unique_by(|x| x.0.clone()).collect_vec();
+    let displayed: HashMap<&String, &String> = lines.iter().map(|x| (&x.0, x.2)).collect();
+    let format_line = |(_, line, k): &(String, String, &String)| {
+        let value = cache.entries.get(*k);
+        let aliases = value.and_then(|x| aliases.get(x));
+        let icon = value.and_then(|x| cache.icons.get(x)).map(String::as_str);
+        backend.format_line(line, aliases.map(Vec::as_slice).unwrap_or_default(), icon)
+    };
+    let mut formatted = lines.iter().map(format_line).collect_vec();
 
-    let histfile =
-        PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".dmenu_drun_histfile");
+    // The `recent` most recently launched entries are shown above the rest
+    // too, separated by `separator`
+    let recent = CONFIG
+        .get("menu", "recent")
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or_default();
+    let separator = CONFIG.get("menu", "separator").unwrap_or("────────");
+    let last_used = |k: &String| cache.entries.get(k).and_then(|x| history.used.get(x));
+    let section = lines
+        .iter()
+        .filter(|x| last_used(x.2).is_some())
+        .sorted_by_key(|x| std::cmp::Reverse(last_used(x.2)))
+        .take(recent)
+        .map(format_line)
+        .collect_vec();
+    if !section.is_empty() {
+        formatted = section
+            .into_iter()
+            .chain(std::iter::once(separator.to_string()))
+            .chain(formatted)
+            .collect();
+    }
 
-    let dmenu = Command::new("dmenu")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .args(["-H", histfile.to_string_lossy().to_string().as_str()])
-        .spawn()
-        .expect("Could not spawn dmenu");
+    speech::say(&tr!("{} entries", names.len()));
+    let prompt = prompt.map(|x| x.replace("{count}", &names.len().to_string()));
+    let output = backend.show(
+        Some(&history.dmenu_histfile()),
+        prompt.as_deref(),
+        &formatted,
+    )?;
+    let output = output.trim().trim_end_matches(".desktop");
+    if !output.is_empty() && output == separator {
+        return Ok(String::new());
+    }
+    // rofi prints the selected line with its markup
+    let output = if markup {
+        menu::strip_markup(output)
+    } else {
+        output.to_string()
+    };
+    Ok(displayed
+        .get(&output)
+        .map_or(output.as_str(), |x| x.as_str())
+        .to_string())
+}
 
-    let mut dmenu_stdin = dmenu.stdin.as_ref().expect("Could not write to dmenu");
+/// The lines to show in the menu, in order, optionally sorted by history.
+/// Entries matching the space
+/// separated `pinned` patterns in the `[menu]` section come before
+/// anything else, in the order of the patterns, followed by the entries
+/// pinned from the menu.
+fn menu_names<'a>(
+    cache: &'a Cache,
+    history: &history::History,
+    sort: Option<history::Sort>,
+) -> Vec<&'a String> {
+    let mut names = cache
+        .entries
+        .keys()
+        .chain(cache.commands.keys())
+        .collect_vec();
+    names.sort_unstable();
+    names.dedup();
+    let mut names = menu::score(names);
+    if let Some(sort) = sort {
+        // Ranked once up front, not on every comparison
+        let rank = |name: &String| {
+            cache
+                .entries
+                .get(name)
+                .map_or(0., |x| history.rank(x, sort))
+        };
+        names = names
+            .into_iter()
+            .map(|x| (rank(x), x))
+            .sorted_by(|a, b| b.0.total_cmp(&a.0))
+            .map(|x| x.1)
+            .collect();
+    }
+    let patterns = CONFIG
+        .get("menu", "pinned")
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect_vec();
+    names.sort_by_key(|name| {
+        let value = cache.entries.get(*name).unwrap_or(name);
+        patterns
+            .iter()
+            .position(|x| config::glob_match(x, name) || config::glob_match(x, value))
+            .or_else(|| {
+                let pinned = history.pinned.iter().position(|x| x == value)?;
+                Some(patterns.len() + pinned)
+            })
+            .unwrap_or(usize::MAX)
+    });
+    names
+}
 
-    let mut formatted = cache.keys().collect_vec();
-    formatted.sort_unstable();
-    formatted.dedup();
-    let formatted = formatted.iter().join("\n");
+/// Prints where the entries come from, what was filtered, and the cache
+/// file's size and age, to find out why something isn't shown.
+fn cache_stats(cache: &Cache, filtered: &[(&str, usize)], flags: &Flags) -> Result {
+    if let Some(path) = flags.cache_path() {
+        let meta = path.metadata()?;
+        let rebuilt = meta
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        println!(
+            "{}",
+            tr!(
+                "Cache: {} ({} bytes, rebuilt {})",
+                path.display(),
+                meta.len(),
+                history::ago(rebuilt)
+            )
+        );
+    } else {
+        println!("{}", tr!("Cache: not used (--no-cache)"));
+    }
+    let desktop_files = cache
+        .entries
+        .values()
+        .filter(|x| x.ends_with(".desktop"))
+        .count();
+    println!(
+        "{}",
+        tr!(
+            "Entries: {} desktop files, {} executables, {} aliases, {} added at runtime",
+            desktop_files,
+            cache.entries.len() - desktop_files,
+            cache.aliases.len(),
+            cache.commands.len()
+        )
+    );
+    let hidden = |key| cache.hidden.iter().filter(|x| x.2 == key).count();
+    println!(
+        "{}",
+        tr!(
+            "Hidden desktop files: {} NoDisplay, {} Hidden, {} OnlyShowIn, {} NotShowIn",
+            hidden("NoDisplay"),
+            hidden("Hidden"),
+            hidden("OnlyShowIn"),
+            hidden("NotShowIn")
+        )
+    );
+    let mut execs = launch::ExecCache::load(flags.cache_path());
+    let missing = cache
+        .execs
+        .values()
+        .filter(|x| !execs.exists(x))
+        .count();
+    println!(
+        "{}",
+        tr!(
+            "Desktop files missing their program: {} (hidden with check_exec)",
+            missing
+        )
+    );
+    print_dir_counts(&DESKTOP_DIRS, is_desktop_file);
+    print_dir_counts(&PATH_DIRS, is_executable);
+    let shadowing = overrides::shadowing();
+    if !shadowing.is_empty() {
+        println!(
+            "{}",
+            tr!(
+                "Local copies shadowing other desktop files ({}): {}",
+                LOCAL_APPLICATIONS.display(),
+                shadowing.join(" ")
+            )
+        );
+    }
+    for (filter, removed) in filtered {
+        println!("{}", tr!("Filter {} removed {}", filter, removed));
+    }
+    print_warnings(cache);
+    Ok(())
+}
 
-    writeln!(dmenu_stdin, "{}", formatted)?;
+/// Checks what's needed to show the menu and launch entries, and prints
+/// what was skipped while scanning. Exits with 1 if anything is wrong.
+fn doctor(cache: &Cache, backend: menu::Backend) -> Result {
+    let mut problems = !cache.warnings.is_empty();
+    match backend.program() {
+        Some(program) => println!("{}", tr!("Menu: {}", program)),
+        None => {
+            problems = true;
+            println!("{}", tr!("Menu: none installed, using the internal menu"));
+        }
+    }
+    match launch::which("gtk-launch") {
+        Some(path) => println!("{}", tr!("gtk-launch: {}", path.display())),
+        None => println!(
+            "{}",
+            tr!("gtk-launch: not installed, desktop files are launched by their Exec")
+        ),
+    }
+    print_dir_counts(&DESKTOP_DIRS, is_desktop_file);
+    print_dir_counts(&PATH_DIRS, is_executable);
+    if cache.warnings.is_empty() {
+        println!("{}", tr!("Nothing was skipped while scanning"));
+    }
+    print_warnings(cache);
+    if problems {
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-    let result = dmenu.wait_with_output().expect("Could not wait for dmenu");
-    let output = String::from_utf8_lossy(&result.stdout)
-        .trim()
-        .trim_end_matches(".desktop")
-        .to_string();
+/// Prints what was skipped while scanning, with why.
+fn print_warnings(cache: &Cache) {
+    if !cache.warnings.is_empty() {
+        println!(
+            "{}",
+            tr!("Skipped while scanning ({}):", cache.warnings.len())
+        );
+    }
+    for (path, reason) in &cache.warnings {
+        println!("  {}: {}", path, reason);
+    }
+}
 
-    let entry = cache.get(&output);
-    if let Some(entry) = entry {
-        if &output == entry {
-            let _ = Command::new(entry)
-                .spawn()
-                .expect("Could not start target executable")
-                .wait();
-        } else {
-            // Gtk-launch spawns a child process, needs double-fork
-            if let Ok(Fork::Child) = daemon(true, true) {
-                let _ = Command::new("gtk-launch")
-                    .arg(entry)
-                    .spawn()
-                    .expect("Could not start target executable")
-                    .wait();
+/// Prints the problems in `files`, or in every desktop file that's scanned.
+/// Exits with 1 if there are any.
+fn lint(mut files: Vec<PathBuf>) -> Result {
+    if files.is_empty() {
+        files = DESKTOP_DIRS
+            .iter()
+            .read_dir_exists_filtered(|x| !is_junk(x) && is_desktop_file(x))
+            .into_iter()
+            .map(|x| x.path())
+            .collect();
+    }
+    let mut failed = false;
+    for file in files {
+        for (line, problem) in desktop::lint(&file)? {
+            failed = true;
+            match line {
+                0 => println!("{}: {}", file.display(), problem),
+                line => println!("{}:{}: {}", file.display(), line, problem),
             }
         }
-    } else {
-        let mut output = output.split_whitespace();
-        let _ = Command::new(output.next().unwrap())
-            .args(output.collect_vec())
-            .spawn()
-            .expect("Could not start target executable")
-            .wait();
     }
-    std::process::exit(result.status.code().unwrap_or(-1));
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints how many entries each of `dirs` has, and how many of them are junk.
+fn print_dir_counts(dirs: &[PathBuf], predicate: fn(&DirEntry) -> bool) {
+    for dir in dirs {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                println!("    {}: {}", dir.display(), tr!("missing"));
+                continue;
+            }
+            Err(e) => {
+                println!("    {}: {}", dir.display(), tr!("unreadable: {}", e));
+                continue;
+            }
+        };
+        let (junk, found): (Vec<_>, Vec<_>) = entries
+            .filter_map(|x| x.ok())
+            .filter(predicate)
+            .partition(is_junk);
+        println!(
+            "    {}: {}",
+            dir.display(),
+            tr!("{} found, {} skipped as junk", found.len(), junk.len())
+        );
+    }
+}
+
+/// Heuristically hides executables in `$PATH` that aren't GUI programs:
+/// those that a desktop file already runs, and those matching any of the
+/// space separated `cli_patterns` in the `[filter]` section (by default
+/// `*-config *.so *.so.* *-linux-gnu-*`).
+fn gui_only(cache: &mut Cache) {
+    let desktop_binaries = cache
+        .aliases
+        .iter()
+        .filter(|(_, v)| v.ends_with(".desktop"))
+        .map(|(k, _)| k.clone())
+        .collect::<std::collections::HashSet<_>>();
+    let patterns = CONFIG
+        .get("filter", "cli_patterns")
+        .unwrap_or("*-config *.so *.so.* *-linux-gnu-*")
+        .split_whitespace()
+        .collect_vec();
+    cache.retain(|_, v| {
+        v.ends_with(".desktop")
+            || !(desktop_binaries.contains(v) || patterns.iter().any(|x| config::glob_match(x, v)))
+    });
+}
+
+/// Looks up the selection by its name, then by its aliases.
+fn resolve<'a>(cache: &'a Cache, output: &str) -> Option<&'a String> {
+    cache
+        .entries
+        .get(output)
+        .or_else(|| cache.aliases.get(output))
 }
 
 #[derive(Clone, Debug, PartialEq, Default)]
-struct Cache(HashMap<String, String>);
+struct Cache {
+    /// Menu line → executable or desktop file name
+    entries: HashMap<String, String>,
+    /// Hidden search terms (e.g. the binary in a desktop file's `Exec`)
+    /// resolving to the same values as `entries`.
+    aliases: HashMap<String, String>,
+    /// Menu line → shell command, for entries added at runtime (e.g. by
+    /// scripts). These are never written to disk.
+    commands: HashMap<String, String>,
+    /// Desktop file name → file of its icon, with `icons = true` in the
+    /// `[menu]` section.
+    icons: HashMap<String, String>,
+    /// Desktop file name → the program it needs, its `TryExec` or else the
+    /// binary in its `Exec`.
+    execs: HashMap<String, String>,
+    /// Desktop file name → its localized `Comment`, for `{comment}` in the
+    /// menu format.
+    comments: HashMap<String, String>,
+    /// Files and directories that were skipped while scanning, with why.
+    warnings: Vec<(String, String)>,
+    /// Desktop files that aren't shown: their name, file name and the key
+    /// hiding them, see `hidden_by`.
+    hidden: Vec<(String, String, String)>,
+}
+
+impl Cache {
+    fn extend(&mut self, other: Cache) {
+        self.entries.extend(other.entries);
+        self.aliases.extend(other.aliases);
+        self.icons.extend(other.icons);
+        self.execs.extend(other.execs);
+        self.comments.extend(other.comments);
+        self.warnings.extend(other.warnings);
+        self.hidden.extend(other.hidden);
+    }
+
+    /// Keeps the entries and aliases for which `predicate(key, value)` holds.
+    fn retain<P: FnMut(&String, &String) -> bool>(&mut self, mut predicate: P) {
+        self.entries.retain(|k, v| predicate(k, v));
+        self.aliases.retain(|k, v| predicate(k, v));
+        self.commands.retain(|k, v| predicate(k, v));
+    }
+}
 
 impl std::fmt::Display for Cache {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for (k, v) in &self.0 {
+        for (k, v) in &self.entries {
             writeln!(f, "{}\0{}", k, v)?;
         }
+        for (k, v) in &self.aliases {
+            writeln!(f, "{}\0{}\0alias", k, v)?;
+        }
+        for (k, v) in &self.icons {
+            writeln!(f, "{}\0{}\0icon", k, v)?;
+        }
+        for (k, v) in &self.execs {
+            writeln!(f, "{}\0{}\0exec", k, v)?;
+        }
+        for (k, v) in &self.comments {
+            writeln!(f, "{}\0{}\0comment", k, v)?;
+        }
+        for (path, reason) in &self.warnings {
+            writeln!(f, "{}\0{}\0warning", path, reason)?;
+        }
+        for (name, file_name, key) in &self.hidden {
+            writeln!(f, "{}\0{}\0{}\0hidden", name, file_name, key)?;
+        }
         Ok(())
     }
 }
@@ -155,93 +1708,417 @@ impl FromStr for Cache {
     type Err = std::fmt::Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        Ok(Self(
-            s.lines()
-                .filter_map(|x| {
-                    x.split('\0')
-                        .map(ToString::to_string)
-                        .collect_tuple::<(_, _)>()
-                })
-                .collect(),
-        ))
+        let mut cache = Self::default();
+        for line in s.lines() {
+            match line.split('\0').collect_vec()[..] {
+                [k, v] => cache.entries.insert(k.to_string(), v.to_string()),
+                [k, v, "alias"] => cache.aliases.insert(k.to_string(), v.to_string()),
+                [k, v, "icon"] => cache.icons.insert(k.to_string(), v.to_string()),
+                [k, v, "exec"] => cache.execs.insert(k.to_string(), v.to_string()),
+                [k, v, "comment"] => cache.comments.insert(k.to_string(), v.to_string()),
+                [path, reason, "warning"] => {
+                    cache.warnings.push((path.to_string(), reason.to_string()));
+                    None
+                }
+                [name, file_name, key, "hidden"] => {
+                    let hidden = (name.to_string(), file_name.to_string(), key.to_string());
+                    cache.hidden.push(hidden);
+                    None
+                }
+                _ => None,
+            };
+        }
+        Ok(cache)
     }
 }
 
-fn create_cache<P: FnMut(&DirEntry) -> bool, L: FnMut(String, &File) -> String>(
-    cache_file: &File,
+/// `localizer` gets the path and the opened file, and returns the name to
+/// show in the menu and optionally a hidden alias for it.
+fn create_cache<P: FnMut(&DirEntry) -> bool, L: FnMut(&Path, &File) -> (String, Option<String>)>(
     dirs: impl Iterator<Item = &'static PathBuf>,
     mut predicate: P,
     mut localizer: L,
 ) -> Result<Cache> {
-    let mut writer = BufWriter::new(cache_file);
     let mut cache = Cache::default();
-    for entry in dirs.read_dir_exists_filtered(|x| predicate(x)) {
+    let mut warn = |path: &Path, reason: String| {
+        cache
+            .warnings
+            .push((path.to_string_lossy().to_string(), reason));
+    };
+    let mut entries = Vec::new();
+    for dir in dirs {
+        match std::fs::read_dir(dir) {
+            Ok(x) => {
+                for entry in x {
+                    match entry {
+                        Ok(x) if !is_junk(&x) && predicate(&x) => entries.push(x),
+                        Ok(_) => {}
+                        Err(e) => warn(dir, tr!("could not list a file: {}", e)),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn(dir, tr!("could not read the directory: {}", e)),
+        }
+    }
+    for entry in entries {
         let file_path = entry.path();
-        let file = File::open(&file_path);
-        if let Ok(file) = file {
-            let file_name = file_path
+        match File::open(&file_path) {
+            Ok(file) => {
+                let file_name = file_path
+                    .file_name()
+                    .ok_or(std::fmt::Error)?
+                    .to_string_lossy()
+                    .to_string();
+                let (name, alias) = localizer(&file_path, &file);
+                if let Some(alias) = alias {
+                    cache.aliases.insert(alias, file_name.clone());
+                }
+                cache.entries.insert(name, file_name);
+            }
+            Err(e) => cache.warnings.push((
+                file_path.to_string_lossy().to_string(),
+                tr!("could not open the file: {}", e),
+            )),
+        }
+    }
+    Ok(cache)
+}
+
+/// Whether a file is hidden, an editor backup, a partial download or empty,
+/// which are never worth showing.
+fn is_junk(entry: &DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy().to_string();
+    name.starts_with('.')
+        || name.ends_with('~')
+        || name.ends_with(".bak")
+        || name.ends_with(".part")
+        || std::fs::metadata(entry.path()).is_ok_and(|x| x.len() == 0)
+}
+
+fn is_desktop_file(entry: &DirEntry) -> bool {
+    if let Some(ext) = entry.path().extension() {
+        ext.to_string_lossy() == "desktop"
+            && entry.metadata().map(|y| y.is_file()).unwrap_or_default()
+    } else {
+        false
+    }
+}
+
+fn is_executable(entry: &DirEntry) -> bool {
+    // Follows symlinks, unlike `DirEntry::metadata`
+    std::fs::metadata(entry.path())
+        .is_ok_and(|meta| meta.is_file() && !meta.permissions().mode() & 0o111 == 0)
+}
+
+fn create_desktop_cache() -> Result<Cache> {
+    let mut packages = Vec::new();
+    let mut icons = Vec::new();
+    let mut execs = HashMap::new();
+    let mut comments = HashMap::new();
+    let mut warnings = Vec::new();
+    // File name → the key hiding it, of the copy that takes precedence
+    let mut hidden = HashMap::new();
+    let mut cache = create_cache(DESKTOP_DIRS.iter(), is_desktop_file, |path, file| {
+        let entry = DesktopEntry::from_reader(path, file);
+        if entry.get("Name").is_none() {
+            warnings.push((
+                path.to_string_lossy().to_string(),
+                tr!("no Name in the [Desktop Entry] group"),
+            ));
+        }
+        let name = entry.get_localized("Name").unwrap_or_default().to_string();
+        packages.push(Package::new(&entry, &name));
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        hidden.insert(file_name.to_string(), hidden_by(&entry));
+        if let Some(icon) = entry.get("Icon") {
+            icons.push((file_name.to_string(), icon.to_string()));
+        }
+        let program = entry
+            .get("TryExec")
+            .map(ToString::to_string)
+            .or_else(|| entry.get("Exec").and_then(desktop::exec_program));
+        if let Some(program) = program {
+            execs.insert(file_name.to_string(), program);
+        }
+        // One line per entry in the cache
+        if let Some(comment) = entry.get_localized("Comment").filter(|x| !x.contains('\n')) {
+            comments.insert(file_name.to_string(), comment.to_string());
+        }
+        (name, entry.get("Exec").and_then(desktop::exec_binary))
+    })?;
+    cache.execs = execs;
+    cache.comments = comments;
+    cache.warnings.extend(warnings);
+    for (file_name, key) in hidden {
+        let Some(key) = key else {
+            continue;
+        };
+        let names = cache
+            .entries
+            .iter()
+            .filter(|x| *x.1 == file_name)
+            .map(|x| x.0.clone())
+            .collect_vec();
+        for name in names {
+            cache.entries.remove(&name);
+            cache.hidden.push((name, file_name.clone(), key.to_string()));
+        }
+        cache.aliases.retain(|_, v| *v != file_name);
+        packages.retain(|x| x.file_name != file_name);
+    }
+    disambiguate_names(&mut cache, &packages);
+    prefer_packaging(&mut cache, packages);
+    // Resolved once here, the theme takes a while to look through
+    if menu::icons_enabled() {
+        let theme = icons::Icons::load();
+        cache.icons = icons
+            .into_iter()
+            .filter_map(|(file_name, icon)| {
+                Some((file_name, theme.find(&icon)?.to_string_lossy().to_string()))
+            })
+            .collect();
+    }
+    Ok(cache)
+}
+
+/// The key that keeps `entry` out of the menu: `Hidden` (it's deleted),
+/// `NoDisplay`, or `OnlyShowIn` / `NotShowIn` for the desktops in
+/// `$XDG_CURRENT_DESKTOP`.
+fn hidden_by(entry: &DesktopEntry) -> Option<&'static str> {
+    let desktops = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    let current = |list: &str| {
+        list.split(';')
+            .any(|x| !x.is_empty() && desktops.split(':').any(|y| x == y))
+    };
+    if entry.get_bool("Hidden") {
+        Some("Hidden")
+    } else if entry.get_bool("NoDisplay") {
+        Some("NoDisplay")
+    } else if entry.get("OnlyShowIn").is_some_and(|x| !current(x)) {
+        Some("OnlyShowIn")
+    } else if entry.get("NotShowIn").is_some_and(current) {
+        Some("NotShowIn")
+    } else {
+        None
+    }
+}
+
+/// Shows desktop files with the same name once for every file, as e.g.
+/// `Terminal (org.gnome.Terminal)`, instead of one hiding the others.
+/// Files shadowed by one with the same file name don't count.
+fn disambiguate_names(cache: &mut Cache, packages: &[Package]) {
+    // Later directories take precedence, like in `create_cache`
+    let current: HashMap<&str, &str> = packages
+        .iter()
+        .map(|x| (x.file_name.as_str(), x.name.as_str()))
+        .collect();
+    let mut files: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (file_name, name) in current {
+        files.entry(name).or_default().push(file_name);
+    }
+    for (name, file_names) in files {
+        if name.is_empty() || file_names.len() < 2 {
+            continue;
+        }
+        cache.entries.remove(name);
+        for file_name in file_names {
+            let id = file_name.trim_end_matches(".desktop");
+            cache
+                .entries
+                .insert(format!("{} ({})", name, id), file_name.to_string());
+        }
+    }
+}
+
+/// How a desktop file was installed, to find the same app packaged twice.
+struct Package {
+    name: String,
+    file_name: String,
+    /// `native`, `flatpak` or `snap`
+    kind: &'static str,
+    wm_class: Option<String>,
+    binary: Option<String>,
+}
+
+impl Package {
+    fn new(entry: &DesktopEntry, name: &str) -> Self {
+        let path = entry.path.to_string_lossy();
+        let exec = entry.get("Exec").unwrap_or_default();
+        let kind = if path.contains("/flatpak/exports/") || exec.contains("flatpak run") {
+            "flatpak"
+        } else if path.contains("/snapd/") || exec.contains("/snap/bin/") {
+            "snap"
+        } else {
+            "native"
+        };
+        // Flatpak runs the app's binary through `flatpak run --command=...`
+        let binary = exec
+            .split_whitespace()
+            .find_map(|x| x.strip_prefix("--command="))
+            .map(ToString::to_string)
+            .or_else(|| desktop::exec_binary(exec));
+        Self {
+            name: name.to_string(),
+            file_name: entry
+                .path
                 .file_name()
-                .ok_or(std::fmt::Error)?
+                .unwrap_or_default()
                 .to_string_lossy()
-                .to_string();
+                .to_string(),
+            kind,
+            wm_class: entry.get("StartupWMClass").map(str::to_lowercase),
+            binary,
+        }
+    }
+
+    fn is_same_app(&self, other: &Self) -> bool {
+        self.file_name != other.file_name
+            && ((self.wm_class.is_some() && self.wm_class == other.wm_class)
+                || (self.binary.is_some() && self.binary == other.binary))
+    }
+}
+
+/// Keeps only the packaging chosen with `prefer` in the `[filter]` section
+/// (`native`, `flatpak` or `snap`) of apps installed in several ways, e.g.
+/// Firefox as a distro package and a Flatpak. Apps are the same when their
+/// `StartupWMClass` or the binary they run match. `all` (the default) keeps
+/// every one.
+fn prefer_packaging(cache: &mut Cache, packages: Vec<Package>) {
+    let prefer = match CONFIG.get("filter", "prefer") {
+        None | Some("all") => return,
+        Some(x @ ("native" | "flatpak" | "snap")) => x,
+        Some(x) => {
+            eprintln!(
+                "dmenu_drun: unknown packaging `{}` to prefer, showing all",
+                x
+            );
+            return;
+        }
+    };
+    for package in &packages {
+        if package.kind == prefer {
+            continue;
+        }
+        let preferred = packages
+            .iter()
+            .find(|x| x.kind == prefer && x.is_same_app(package));
+        if let Some(preferred) = preferred {
+            cache.retain(|_, v| *v != package.file_name);
+            // It may have been shadowed by, or disambiguated from, the other
+            // one's name
+            cache.entries.retain(|_, v| *v != preferred.file_name);
             cache
-                .0
-                .insert(localizer(file_name.clone(), &file), file_name);
+                .entries
+                .insert(preferred.name.clone(), preferred.file_name.clone());
         }
     }
-    write!(writer, "{}", cache)?;
+}
+
+fn create_path_cache() -> Result<Cache> {
+    // Symlink target → names linking to it, to find multi-call binaries
+    let mut targets: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    // Name → where it was found, in `$PATH` order
+    let mut locations: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut cache = create_cache(PATH_DIRS.iter(), is_executable, |path, _| {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if path.is_symlink() {
+            if let Ok(target) = path.canonicalize() {
+                targets.entry(target).or_default().push(name.clone());
+            }
+        }
+        locations
+            .entry(name.clone())
+            .or_default()
+            .push(path.to_path_buf());
+        (name, None)
+    })?;
+    collapse_multicall(&mut cache, targets);
+    disambiguate(&mut cache, locations);
     Ok(cache)
 }
 
-fn create_desktop_cache(cache_file: &File) -> Result<Cache> {
-    create_cache(
-        cache_file,
-        DESKTOP_DIRS.iter(),
-        |x| {
-            if let Some(ext) = x.path().extension() {
-                ext.to_string_lossy() == "desktop"
-                    && x.metadata().map(|y| y.is_file()).unwrap_or_default()
+/// Shows executables that exist in multiple `$PATH` directories once for
+/// every directory, as e.g. `python (~/.local/bin)`. The first one is what
+/// the name runs and keeps it as an alias, the others run their full path.
+/// Paths to the same file (like `/bin` and `/usr/bin` on merged-usr
+/// systems) only count once.
+fn disambiguate(cache: &mut Cache, locations: HashMap<String, Vec<PathBuf>>) {
+    let home = dirs::home_dir().unwrap_or_default();
+    for (name, paths) in locations {
+        let mut files = Vec::new();
+        let paths = paths
+            .into_iter()
+            .filter(|x| {
+                let file = x.canonicalize().unwrap_or_else(|_| x.clone());
+                let new = !files.contains(&file);
+                files.push(file);
+                new
+            })
+            .collect_vec();
+        if paths.len() < 2 || cache.entries.remove(&name).is_none() {
+            continue;
+        }
+        for (i, path) in paths.iter().enumerate() {
+            let dir = path.parent().unwrap_or(Path::new("/"));
+            let dir = match dir.strip_prefix(&home) {
+                Ok(x) if !home.as_os_str().is_empty() => Path::new("~").join(x),
+                _ => dir.to_path_buf(),
+            };
+            let line = format!("{} ({})", name, dir.display());
+            if i == 0 {
+                cache.entries.insert(line, name.clone());
+                cache.aliases.insert(name.clone(), name.clone());
             } else {
-                false
+                cache
+                    .entries
+                    .insert(line, path.to_string_lossy().to_string());
             }
-        },
-        |_, file| {
-            let bufreader = BufReader::new(file);
-            bufreader
-                .lines()
-                .filter_map(|x| x.ok())
-                .find(|x| x.starts_with("Name="))
-                .unwrap_or_default()
-                .trim_start_matches("Name=")
-                .to_string()
-        },
-    )
-}
-
-fn create_path_cache(cache_file: &File) -> Result<Cache> {
-    create_cache(
-        cache_file,
-        PATH_DIRS.iter(),
-        |x| {
-            x.metadata()
-                .map(|meta| !meta.permissions().mode() & 0o111)
-                .contains(&0)
-                && x.metadata().map(|y| y.is_file()).unwrap_or_default()
-        },
-        |name, _| name,
-    )
+        }
+    }
+}
+
+/// Handles multi-call binaries like busybox, where many names in `$PATH`
+/// are symlinks to one binary, according to `multicall` in the `[filter]`
+/// section: `hide` removes these applets, `tag` shows them as
+/// `ls (busybox)`. Binaries count as multi-call when at least
+/// `multicall_threshold` (default 10) names link to them.
+fn collapse_multicall(cache: &mut Cache, targets: HashMap<PathBuf, Vec<String>>) {
+    let mode = CONFIG.get("filter", "multicall");
+    if !matches!(mode, Some("hide" | "tag")) {
+        return;
+    }
+    let threshold = CONFIG
+        .get("filter", "multicall_threshold")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(10);
+    for (target, names) in targets.into_iter().filter(|x| x.1.len() >= threshold) {
+        let binary = target
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        for name in names.into_iter().filter(|x| *x != binary) {
+            cache.entries.remove(&name);
+            if mode == Some("tag") {
+                cache
+                    .entries
+                    .insert(format!("{} ({})", name, binary), name.clone());
+            }
+        }
+    }
 }
 
 /// Trait used to return an `Iterator` over all `DirEntry`'s
 /// that exist
 trait ReadDirExists: Sized {
     /// Returns all `Direntry`'s in the directories in a
-    /// given iterator that exists.
+    /// given iterator that exists, for which `predicate` holds.
     /// i.e. filter everything out that does not exist.
-    fn read_dir_exists(self) -> Vec<DirEntry> {
-        self.read_dir_exists_filtered(|_| true)
-    }
-    /// See `read_dir_exists`. Applies a filter, before collecting.
     fn read_dir_exists_filtered<P: FnMut(&DirEntry) -> bool>(self, predicate: P) -> Vec<DirEntry>;
 }
 
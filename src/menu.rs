@@ -0,0 +1,561 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+
+use crate::config::CONFIG;
+use crate::{launch, Result};
+
+/// The program used to show the menu, configured with `backend` in the
+/// `[menu]` section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Dmenu,
+    Rofi,
+    Bemenu,
+    Fuzzel,
+    Wofi,
+    /// Asks on the terminal, see `internal`
+    Internal,
+}
+
+impl Backend {
+    /// Without a configured backend, the first installed of fuzzel, bemenu,
+    /// wofi, rofi and dmenu is used on Wayland, and of dmenu, rofi and
+    /// bemenu on X11. A configured backend that isn't installed falls back
+    /// the same way, and the internal menu is used if none of them are.
+    pub fn from_config() -> Self {
+        let configured = match CONFIG.get("menu", "backend") {
+            Some("dmenu") => Some(Self::Dmenu),
+            Some("rofi") => Some(Self::Rofi),
+            Some("bemenu") => Some(Self::Bemenu),
+            Some("fuzzel") => Some(Self::Fuzzel),
+            Some("wofi") => Some(Self::Wofi),
+            Some("internal") => Some(Self::Internal),
+            None => None,
+            Some(x) => {
+                eprintln!("dmenu_drun: unknown backend `{}`, ignoring", x);
+                None
+            }
+        };
+        let chain: &[Self] = if is_wayland() {
+            // dmenu and rofi still work through XWayland
+            &[
+                Self::Fuzzel,
+                Self::Bemenu,
+                Self::Wofi,
+                Self::Rofi,
+                Self::Dmenu,
+            ]
+        } else {
+            &[Self::Dmenu, Self::Rofi, Self::Bemenu]
+        };
+        let preferred = configured.unwrap_or(chain[0]);
+        if preferred.is_installed() {
+            return preferred;
+        }
+        let fallback = chain
+            .iter()
+            .copied()
+            .find(|x| x.is_installed())
+            .unwrap_or(Self::Internal);
+        // Picking another default isn't worth a warning
+        if configured.is_some() || fallback == Self::Internal {
+            eprintln!(
+                "dmenu_drun: {} is not installed, using {}",
+                preferred.program().unwrap_or_default(),
+                fallback.program().unwrap_or("the internal menu")
+            );
+        }
+        fallback
+    }
+
+    pub fn program(self) -> Option<&'static str> {
+        match self {
+            Self::Dmenu => Some("dmenu"),
+            Self::Rofi => Some("rofi"),
+            Self::Bemenu => Some("bemenu"),
+            Self::Fuzzel => Some("fuzzel"),
+            Self::Wofi => Some("wofi"),
+            Self::Internal => None,
+        }
+    }
+
+    /// With `markup = true` in the `[menu]` section, for rofi.
+    fn markup(self) -> bool {
+        self == Self::Rofi && CONFIG.get("menu", "markup") == Some("true")
+    }
+
+    fn is_installed(self) -> bool {
+        self.program().is_none_or(|x| launch::which(x).is_some())
+    }
+
+    /// Builds the command that shows the menu, with the placement options
+    /// from the config translated to the backend's flags. `entries` is the
+    /// amount of lines that will be shown.
+    pub fn command(self, histfile: Option<&Path>, prompt: Option<&str>, entries: usize) -> Command {
+        let mut command = match self {
+            Self::Dmenu => {
+                let mut command = Command::new("dmenu");
+                if let Some(histfile) = histfile {
+                    command.args(["-H", histfile.to_string_lossy().to_string().as_str()]);
+                }
+                command
+            }
+            Self::Rofi => {
+                let mut command = Command::new("rofi");
+                command.arg("-dmenu");
+                command
+            }
+            Self::Bemenu => Command::new("bemenu"),
+            Self::Fuzzel => {
+                let mut command = Command::new("fuzzel");
+                command.arg("--dmenu");
+                command
+            }
+            Self::Wofi => {
+                let mut command = Command::new("wofi");
+                command.arg("--dmenu");
+                command
+            }
+            Self::Internal => unreachable!("the internal menu has no command"),
+        };
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .args(self.placement_args());
+        if let Some(prompt) = prompt {
+            command.args(["-p", prompt]);
+        }
+        if let Some(lines) = lines(entries) {
+            // wofi's `-l` is the location
+            let flag = if self == Self::Wofi { "-L" } else { "-l" };
+            command.args([flag.to_string(), lines.to_string()]);
+        }
+        if let Some(args) = CONFIG.get("menu", "args") {
+            command.args(args.split_whitespace());
+        }
+        // Only the main menu has history, and only its lines are markup
+        if histfile.is_some() && self.markup() {
+            command.arg("-markup-rows");
+        }
+        if self == Self::Wofi && icons_enabled() {
+            command.arg("--allow-images");
+        }
+        command
+    }
+
+    /// Translates the `monitor`, `position` and `width` keys.
+    /// `monitor` is either a monitor index, `focused` or `mouse`;
+    /// `position` is `top`, `bottom` or `center`; `width` is a percentage
+    /// of the screen width.
+    fn placement_args(self) -> Vec<String> {
+        let mut args = Vec::new();
+        let monitor = CONFIG.get("menu", "monitor");
+        let position = CONFIG.get("menu", "position");
+        let width = CONFIG.get("menu", "width").and_then(|x| x.parse::<u32>().ok());
+        match self {
+            Self::Dmenu => {
+                // dmenu follows the focused monitor by default.
+                if let Some(index) = monitor.filter(|x| x.parse::<u32>().is_ok()) {
+                    args.extend(["-m".to_string(), index.to_string()]);
+                }
+                if position == Some("bottom") {
+                    args.push("-b".to_string());
+                }
+            }
+            Self::Rofi => {
+                let monitor = match monitor {
+                    Some("focused") => Some("-4"),
+                    Some("mouse") => Some("-5"),
+                    x => x,
+                };
+                if let Some(monitor) = monitor {
+                    args.extend(["-monitor".to_string(), monitor.to_string()]);
+                }
+                let location = match position {
+                    Some("top") => Some("2"),
+                    Some("bottom") => Some("6"),
+                    Some("center") => Some("0"),
+                    _ => None,
+                };
+                if let Some(location) = location {
+                    args.extend(["-location".to_string(), location.to_string()]);
+                }
+                if let Some(width) = width {
+                    args.extend([
+                        "-theme-str".to_string(),
+                        format!("window {{ width: {}%; }}", width),
+                    ]);
+                }
+            }
+            Self::Bemenu => {
+                if let Some(monitor) = monitor.filter(|x| *x != "mouse") {
+                    args.extend(["-m".to_string(), monitor.to_string()]);
+                }
+                match position {
+                    Some("bottom") => args.push("-b".to_string()),
+                    Some("center") => args.push("-c".to_string()),
+                    _ => {}
+                }
+                if let Some(width) = width {
+                    args.extend(["-W".to_string(), format!("{}", width as f32 / 100.)]);
+                }
+            }
+            // fuzzel only knows outputs by name, and widths in characters
+            Self::Fuzzel => {
+                if let Some(position) = position {
+                    args.extend(["--anchor".to_string(), position.to_string()]);
+                }
+            }
+            Self::Wofi => {
+                if let Some(position) = position {
+                    args.extend(["--location".to_string(), position.to_string()]);
+                }
+                if let Some(width) = width {
+                    args.extend(["--width".to_string(), format!("{}%", width)]);
+                }
+            }
+            Self::Internal => {}
+        }
+        args
+    }
+}
+
+/// Something that lets the user pick one of `lines`, or type something
+/// else. Everything that's shown goes through this, so the whole flow can
+/// be run without a display.
+pub trait Menu {
+    /// Shows `lines`, returns the selection or what was typed. `histfile`
+    /// is only supported by dmenu.
+    fn show(
+        &self,
+        histfile: Option<&Path>,
+        prompt: Option<&str>,
+        lines: &[String],
+    ) -> Result<String>;
+
+    /// Formats a line before it's shown, `aliases` are hidden search terms
+    /// and `icon` is the file of the entry's icon.
+    fn format_line(&self, name: &str, _aliases: &[&String], _icon: Option<&str>) -> String {
+        name.to_string()
+    }
+
+    /// Whether the lines of the main menu are Pango markup.
+    fn markup(&self) -> bool {
+        false
+    }
+
+    /// Shows `lines` without history.
+    fn choose(&self, prompt: &str, lines: &[String]) -> Result<String> {
+        self.show(None, Some(prompt), lines)
+    }
+
+    /// Asks a yes/no question, "no" comes first so it's the default.
+    fn confirm(&self, prompt: &str) -> Result<bool> {
+        let yes = crate::tr!("yes");
+        Ok(self.choose(prompt, &[crate::tr!("no"), yes.clone()])? == yes)
+    }
+}
+
+/// A backend with the options from the command line that change how its
+/// menus are shown.
+#[derive(Clone, Copy, Debug)]
+pub struct Picker {
+    pub backend: Backend,
+    /// With `--auto-select`, rofi accepts the last line left while typing
+    pub auto_select: bool,
+    /// With `--timeout <seconds>`, menus close when nothing was selected
+    /// in time
+    pub timeout: Option<Duration>,
+}
+
+impl Menu for Picker {
+    fn show(
+        &self,
+        histfile: Option<&Path>,
+        prompt: Option<&str>,
+        lines: &[String],
+    ) -> Result<String> {
+        if self.backend == Backend::Internal {
+            return internal(prompt, lines);
+        }
+        let mut command = self.backend.command(histfile, prompt, lines.len());
+        // Only rofi can accept the last line left while typing
+        if self.backend == Backend::Rofi && self.auto_select {
+            command.arg("-auto-select");
+        }
+        let mut menu = command
+            .spawn()
+            .map_err(|e| crate::tr!("Could not spawn menu: {}", e))?;
+        // Always wait for the menu, even if it went away before reading.
+        let written = writeln!(
+            menu.stdin.take().expect("Could not write to menu"),
+            "{}",
+            lines.join("\n")
+        );
+        if let Some(timeout) = self.timeout {
+            let deadline = Instant::now() + timeout;
+            while menu.try_wait()?.is_none() {
+                if Instant::now() >= deadline {
+                    // Not reading the output, which children of the menu
+                    // could keep open
+                    menu.kill()?;
+                    menu.wait()?;
+                    TIMED_OUT.store(true, Ordering::Relaxed);
+                    return Ok(String::new());
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+        let output = menu.wait_with_output()?;
+        written?;
+        let output = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        // wofi prints the image along with the selected line
+        Ok(match output.strip_prefix("img:") {
+            Some(x) if self.backend == Backend::Wofi => x
+                .split_once(":text:")
+                .map_or(output.clone(), |x| x.1.to_string()),
+            _ => output,
+        })
+    }
+
+    fn markup(&self) -> bool {
+        self.backend.markup()
+    }
+
+    /// Formats a menu line. `aliases` are hidden search terms, which only
+    /// rofi supports. Icons are shown by rofi, fuzzel and wofi.
+    fn format_line(&self, name: &str, aliases: &[&String], icon: Option<&str>) -> String {
+        // Only worth it if they can't be found by the name already
+        let aliases = aliases
+            .iter()
+            .filter(|x| !name.to_lowercase().contains(&x.to_lowercase()))
+            .join(" ");
+        let icon = icon.filter(|_| icons_enabled());
+        // rofi and fuzzel take options after a NUL, separated by 0x1f
+        let mut options = Vec::new();
+        if let (Backend::Rofi | Backend::Fuzzel, Some(icon)) = (self.backend, icon) {
+            options.push(format!("icon\x1f{}", icon));
+        }
+        if self.backend == Backend::Rofi && !aliases.is_empty() {
+            options.push(format!("meta\x1f{}", aliases));
+        }
+        match (self.backend, icon) {
+            (Backend::Wofi, Some(icon)) => format!("img:{}:text:{}", icon, name),
+            _ if !options.is_empty() => format!("{}\0{}", name, options.join("\x1f")),
+            _ => name.to_string(),
+        }
+    }
+}
+
+/// `text` escaped for Pango markup, in a span with `attributes` (e.g.
+/// `weight="bold"`) if there are any.
+pub fn span(text: &str, attributes: &str) -> String {
+    let text = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    if attributes.is_empty() || text.is_empty() {
+        text
+    } else {
+        format!("<span {}>{}</span>", attributes, text)
+    }
+}
+
+/// The text of a line of Pango markup, without its tags.
+pub fn strip_markup(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Whether entries are shown with their icons, with `icons = true` in the
+/// `[menu]` section.
+pub fn icons_enabled() -> bool {
+    CONFIG.get("menu", "icons") == Some("true")
+}
+
+/// Set when a menu was closed by `--timeout`.
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Whether a menu was closed because nothing was selected within
+/// `--timeout`, rather than by the user.
+pub fn timed_out() -> bool {
+    TIMED_OUT.load(Ordering::Relaxed)
+}
+
+/// Whether we're running in a Wayland session.
+pub fn is_wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|x| x == "wayland")
+}
+
+/// A last resort when no menu program is installed: asks on the terminal,
+/// and picks the best match for what was typed. Typing something that
+/// matches nothing returns it as is.
+fn internal(prompt: Option<&str>, lines: &[String]) -> Result<String> {
+    let mut tty = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| {
+            crate::tr!("No menu program is installed, and there's no terminal to ask on")
+        })?;
+    write!(tty, "{} ", prompt.unwrap_or(">"))?;
+    let mut query = String::new();
+    BufReader::new(&tty).read_line(&mut query)?;
+    let query = query.trim();
+    Ok(fuzzy_match(query, lines).unwrap_or(query).to_string())
+}
+
+/// Finds the line that best matches `query`, ignoring case: an exact match,
+/// then a prefix, a substring and finally the characters in order. Earlier
+/// lines win ties.
+fn fuzzy_match<'a>(query: &str, lines: &'a [String]) -> Option<&'a str> {
+    if query.is_empty() {
+        return None;
+    }
+    let query = query.to_lowercase();
+    lines
+        .iter()
+        .filter_map(|line| {
+            let lower = line.to_lowercase();
+            let rank = if lower == query {
+                0
+            } else if lower.starts_with(&query) {
+                1
+            } else if lower.contains(&query) {
+                2
+            } else {
+                let mut chars = lower.chars();
+                if !query.chars().all(|c| chars.any(|x| x == c)) {
+                    return None;
+                }
+                3
+            };
+            Some((rank, line.as_str()))
+        })
+        .min_by_key(|x| x.0)
+        .map(|x| x.1)
+}
+
+/// The amount of lines for a vertical list, configured with `lines` in the
+/// `[menu]` section. `auto` fits the list to the amount of entries, but at
+/// most `max_lines` (default 20) and half the screen, based on
+/// `line_height` (default 24 pixels).
+fn lines(entries: usize) -> Option<usize> {
+    match CONFIG.get("menu", "lines")? {
+        "auto" => {
+            let max_lines = CONFIG
+                .get("menu", "max_lines")
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(20);
+            let line_height = CONFIG
+                .get("menu", "line_height")
+                .and_then(|x| x.parse().ok())
+                .filter(|x| *x > 0)
+                .unwrap_or(24);
+            let fits = screen_height().map_or(max_lines, |x| x / 2 / line_height);
+            Some(entries.min(max_lines).min(fits).max(1))
+        }
+        x => x.parse().ok(),
+    }
+}
+
+/// Height of the smallest connected screen, in pixels.
+/// Read from sysfs to not depend on the display server.
+fn screen_height() -> Option<usize> {
+    std::fs::read_dir("/sys/class/drm")
+        .ok()?
+        .filter_map(|x| x.ok())
+        .filter(|x| {
+            std::fs::read_to_string(x.path().join("status"))
+                .is_ok_and(|x| x.trim() == "connected")
+        })
+        .filter_map(|x| {
+            // The first mode is the preferred one, e.g. `1920x1080`
+            let modes = std::fs::read_to_string(x.path().join("modes")).ok()?;
+            modes.lines().next()?.split_once('x')?.1.parse().ok()
+        })
+        .min()
+}
+
+/// Lets the `scorer` from the `[general]` section reorder the menu lines.
+/// It gets the lines on stdin, and prints them in the order they should be
+/// shown, optionally prefixed with a score and a tab to sort on (highest
+/// first). Lines it leaves out are shown after the ones it printed.
+pub fn score(lines: Vec<&String>) -> Vec<&String> {
+    let Some(scorer) = CONFIG.get("general", "scorer") else {
+        return lines;
+    };
+    let output = Command::new("sh")
+        .args(["-c", scorer])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| std::io::Error::other("no stdin"))?;
+            let input = lines.iter().join("\n");
+            // Written while the output is read, a scorer printing as it
+            // reads would block on a full pipe otherwise
+            std::thread::scope(|scope| {
+                let writer = scope.spawn(move || stdin.write_all(input.as_bytes()));
+                let output = child.wait_with_output();
+                writer
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+                output
+            })
+        });
+    let output = match output {
+        Ok(x) if x.status.success() => String::from_utf8_lossy(&x.stdout).to_string(),
+        _ => {
+            eprintln!("dmenu_drun: scorer `{}` failed, ignoring", scorer);
+            return lines;
+        }
+    };
+
+    let by_line: HashMap<&str, &String> = lines.iter().map(|x| (x.as_str(), *x)).collect();
+    let mut scored = output
+        .lines()
+        .enumerate()
+        .filter_map(|(i, x)| {
+            let (score, line) = match x.split_once('\t') {
+                Some((score, line)) => (score.parse::<f64>().ok()?, line),
+                None => (0., x),
+            };
+            Some((score, i, *by_line.get(line)?))
+        })
+        .collect_vec();
+    // Sort on score, keeping the scorer's order for equal scores
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    let mut scored = scored.into_iter().map(|x| x.2).unique().collect_vec();
+    let printed: HashSet<&String> = scored.iter().copied().collect();
+    let rest = lines
+        .into_iter()
+        .filter(|x| !printed.contains(x))
+        .collect_vec();
+    scored.extend(rest);
+    scored
+}
@@ -0,0 +1,85 @@
+//! Default applications, as stored in `mimeapps.list`.
+//! See also: https://specifications.freedesktop.org/mime-apps-spec/latest/
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::config_dirs;
+use crate::Result;
+
+const DEFAULTS: &str = "[Default Applications]";
+
+/// The user's `mimeapps.list`, where defaults are written.
+fn user_path() -> PathBuf {
+    dirs::config_dir().unwrap_or_default().join("mimeapps.list")
+}
+
+/// The MIME type of `file`, from xdg-mime or else file(1).
+pub fn filetype(file: &Path) -> Option<String> {
+    [
+        Command::new("xdg-mime")
+            .args(["query", "filetype"])
+            .arg(file),
+        Command::new("file")
+            .args(["--brief", "--mime-type"])
+            .arg(file),
+    ]
+    .iter_mut()
+    .find_map(|command| {
+        let output = command.stderr(Stdio::null()).output().ok()?;
+        let mime = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && mime.contains('/')).then_some(mime)
+    })
+}
+
+/// The desktop file that opens `mime` by default, from the first
+/// `mimeapps.list` in the config directories that sets one.
+pub fn default_for(mime: &str) -> Option<String> {
+    config_dirs().into_iter().find_map(|dir| {
+        let list = std::fs::read_to_string(dir.join("mimeapps.list")).ok()?;
+        let mut in_defaults = false;
+        list.lines().map(str::trim).find_map(|line| {
+            if line.starts_with('[') {
+                in_defaults = line == DEFAULTS;
+                return None;
+            }
+            let (k, v) = line.split_once('=').filter(|_| in_defaults)?;
+            let app = v.split(';').map(str::trim).find(|x| !x.is_empty())?;
+            (k.trim() == mime).then(|| app.to_string())
+        })
+    })
+}
+
+/// Makes `app` (a desktop file name) the default for `mime` in the user's
+/// `mimeapps.list`. The rest of the file is left as it is.
+pub fn set_default(mime: &str, app: &str) -> Result {
+    let path = user_path();
+    let list = std::fs::read_to_string(&path).unwrap_or_default();
+    let mut lines = list.lines().map(ToString::to_string).collect::<Vec<_>>();
+    let line = format!("{}={};", mime, app);
+    match lines.iter().position(|x| x.trim() == DEFAULTS) {
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|x| x.trim().starts_with('['))
+                .map_or(lines.len(), |x| start + 1 + x);
+            let existing = lines[start + 1..end]
+                .iter()
+                .position(|x| x.split_once('=').is_some_and(|(k, _)| k.trim() == mime));
+            match existing {
+                Some(i) => lines[start + 1 + i] = line,
+                None => lines.insert(start + 1, line),
+            }
+        }
+        None => {
+            if lines.last().is_some_and(|x| !x.is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(DEFAULTS.to_string());
+            lines.push(line);
+        }
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::write(&path, lines.join("\n") + "\n")?)
+}
@@ -0,0 +1,170 @@
+//! Network connections for `dmenu_drun net`, through NetworkManager with
+//! nmcli.
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use itertools::Itertools;
+
+use crate::Result;
+
+/// Saved connections that are listed besides Wi-Fi networks.
+const CONNECTION_TYPES: [&str; 3] = ["vpn", "wireguard", "802-3-ethernet"];
+
+#[derive(Clone, Debug)]
+pub enum Kind {
+    /// A network in range, with its signal strength and security
+    Wifi {
+        signal: String,
+        security: String,
+        saved: bool,
+    },
+    /// A saved connection of one of `CONNECTION_TYPES`
+    Saved(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Network {
+    /// The SSID, or the name of the connection
+    pub name: String,
+    pub kind: Kind,
+    pub active: bool,
+}
+
+/// Runs nmcli in terse mode, returning the fields of each line.
+fn nmcli(args: &[&str]) -> Result<Vec<Vec<String>>> {
+    let output = Command::new("nmcli")
+        .arg("-t")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| crate::tr!("Could not run nmcli: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(fields)
+        .collect())
+}
+
+/// Splits a line of `nmcli -t` on `:`, where `\:` and `\\` are escapes.
+fn fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().expect("not empty").extend(chars.next()),
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().expect("not empty").push(c),
+        }
+    }
+    fields
+}
+
+/// The Wi-Fi networks in range, strongest first, then the saved VPN,
+/// WireGuard and ethernet connections.
+pub fn list() -> Result<Vec<Network>> {
+    let connections = nmcli(&["-f", "NAME,TYPE,ACTIVE", "connection", "show"])?;
+    let wifi = nmcli(&[
+        "-f",
+        "IN-USE,SSID,SIGNAL,SECURITY",
+        "device",
+        "wifi",
+        "list",
+    ])?;
+    let networks = wifi
+        .into_iter()
+        .filter_map(|x| match &x[..] {
+            [in_use, ssid, signal, security] if !ssid.is_empty() => Some(Network {
+                name: ssid.clone(),
+                kind: Kind::Wifi {
+                    signal: signal.clone(),
+                    security: security.clone(),
+                    saved: connections.iter().any(|x| {
+                        x.first() == Some(ssid) && x.get(1).is_some_and(|x| x == "802-11-wireless")
+                    }),
+                },
+                active: in_use == "*",
+            }),
+            _ => None,
+        })
+        .unique_by(|x| x.name.clone());
+    let saved = connections.iter().filter_map(|x| match &x[..] {
+        [name, kind, active] if CONNECTION_TYPES.contains(&kind.as_str()) => Some(Network {
+            name: name.clone(),
+            kind: Kind::Saved(kind.clone()),
+            active: active == "yes",
+        }),
+        _ => None,
+    });
+    Ok(networks.chain(saved).collect())
+}
+
+impl Network {
+    /// How the network is shown in the menu, `●` when connected.
+    pub fn display(&self) -> String {
+        let status = if self.active { "●" } else { "○" };
+        let details = match &self.kind {
+            Kind::Wifi {
+                signal, security, ..
+            } if security.is_empty() => format!("Wi-Fi {}%", signal),
+            Kind::Wifi {
+                signal, security, ..
+            } => format!("Wi-Fi {}% {}", signal, security),
+            Kind::Saved(kind) if kind == "802-3-ethernet" => "Ethernet".to_string(),
+            Kind::Saved(kind) if kind == "vpn" => "VPN".to_string(),
+            Kind::Saved(kind) => kind.clone(),
+        };
+        format!("{} {} — {}", status, self.name, details)
+    }
+
+    /// Whether connecting needs a password, for secured networks that
+    /// haven't been connected to before.
+    pub fn needs_password(&self) -> bool {
+        matches!(&self.kind, Kind::Wifi { security, saved, .. } if !saved && !security.is_empty())
+    }
+
+    /// Connects, or disconnects if the network is connected already.
+    /// `password` is a file made by `password_file`, given to nmcli on
+    /// stdin and removed afterwards.
+    pub fn command(&self, password: Option<&Path>) -> Command {
+        let mut command = Command::new("nmcli");
+        match (&self.kind, self.active, password) {
+            (_, true, _) => command.args(["connection", "down", "id", &self.name]),
+            (Kind::Wifi { saved: false, .. }, false, Some(password)) => {
+                let mut command = Command::new("sh");
+                command
+                    .args([
+                        "-c",
+                        "nmcli --ask device wifi connect \"$1\" < \"$2\"; rm -f -- \"$2\"",
+                        "sh",
+                        &self.name,
+                    ])
+                    .arg(password);
+                return command;
+            }
+            (Kind::Wifi { saved: false, .. }, false, None) => {
+                command.args(["device", "wifi", "connect", &self.name])
+            }
+            (_, false, _) => command.args(["connection", "up", "id", &self.name]),
+        };
+        command
+    }
+}
+
+/// Writes `password` to a file only we can read, for `Network::command`.
+/// On nmcli's command line, every user could see it.
+pub fn password_file(password: &str) -> Result<PathBuf> {
+    let path = dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("dmenu_drun-net-{}", std::process::id()));
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    writeln!(file, "{}", password)?;
+    Ok(path)
+}
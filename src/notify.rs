@@ -0,0 +1,59 @@
+use std::process::{Command, Stdio};
+
+use crate::config::CONFIG;
+
+/// A command that shows `message` (or "Time is up") as a notification
+/// after `seconds`. It's a transient systemd timer if possible, so it shows
+/// up in `systemctl --user list-timers` and can be stopped, else a
+/// sleeping shell.
+pub fn timer(seconds: u64, message: Option<&str>) -> Command {
+    let message = message
+        .map(ToString::to_string)
+        .unwrap_or_else(|| crate::tr!("Time is up"));
+    let notify = [
+        "notify-send",
+        "-a",
+        "dmenu_drun",
+        "-u",
+        "critical",
+        &message,
+    ];
+    if crate::launch::which("systemd-run").is_some() {
+        let mut command = Command::new("systemd-run");
+        command
+            .args(["--user", "--quiet", "--collect"])
+            .arg(format!("--on-active={}", seconds))
+            .arg("--timer-property=AccuracySec=1s")
+            .args(notify);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "sleep \"$0\" && exec \"$@\""])
+            .arg(seconds.to_string())
+            .args(notify);
+        command
+    }
+}
+
+/// Shows a "Starting ..." notification with the entry's icon if any of
+/// `names` matches a pattern in the `[notify]` section, as feedback for
+/// programs that take a while to open a window.
+pub fn starting(names: &[&str], icon: Option<&str>) {
+    if CONFIG.lookup("notify", names) != Some("true") {
+        return;
+    }
+    let mut command = Command::new("notify-send");
+    command.args(["-a", "dmenu_drun", "-h", "int:transient:1"]);
+    if let Some(icon) = icon {
+        command.args(["-i", icon]);
+    }
+    let shown = command
+        .arg(crate::tr!("Starting {}…", names[0]))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status();
+    if let Err(e) = shown {
+        eprintln!("dmenu_drun: could not run notify-send: {}", e);
+    }
+}
@@ -0,0 +1,281 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use crate::desktop::DesktopEntry;
+use crate::{launch, Result, DESKTOP_DIRS, LOCAL_APPLICATIONS};
+
+/// Copies a desktop file into `LOCAL_APPLICATIONS`, where it shadows the
+/// system's copy and can be changed freely. Returns the path of the copy.
+pub fn make_local(file_name: &str) -> Result<PathBuf> {
+    let local = LOCAL_APPLICATIONS.join(file_name);
+    if !local.exists() {
+        let entry =
+            DesktopEntry::find(file_name).ok_or_else(|| crate::tr!("Desktop file not found"))?;
+        std::fs::create_dir_all(&*LOCAL_APPLICATIONS)?;
+        std::fs::copy(&entry.path, &local)?;
+    }
+    Ok(local)
+}
+
+/// The desktop files in `LOCAL_APPLICATIONS` that shadow one with the same
+/// name elsewhere, like the copies `edit` and `tweak` make, sorted.
+pub fn shadowing() -> Vec<String> {
+    let mut names = std::fs::read_dir(&*LOCAL_APPLICATIONS)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .filter(|x| x.ends_with(".desktop"))
+        .filter(|x| {
+            DESKTOP_DIRS
+                .iter()
+                .filter(|dir| **dir != *LOCAL_APPLICATIONS)
+                .any(|dir| dir.join(x).is_file())
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+/// Deletes the local copy of a desktop file after asking through the menu,
+/// so the one it shadows is used again. Desktop files that only exist
+/// locally are never deleted. Returns whether it was deleted.
+pub fn restore(file_name: &str, menu: &dyn crate::menu::Menu) -> Result<bool> {
+    if !shadowing().iter().any(|x| x == file_name) {
+        return Err(crate::tr!("{} has no local copy to remove", file_name).into());
+    }
+    if !menu.confirm(&crate::tr!("Delete your copy of {}?", file_name))? {
+        return Ok(false);
+    }
+    std::fs::remove_file(LOCAL_APPLICATIONS.join(file_name))?;
+    Ok(true)
+}
+
+/// Opens (a local copy of) a desktop file in `$VISUAL` or `$EDITOR`, in
+/// a terminal unless we're already running in one.
+pub fn edit(file_name: &str) -> Result {
+    let path = make_local(file_name)?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut argv = editor
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    argv.push(path.to_string_lossy().to_string());
+    if std::io::stdout().is_terminal() {
+        launch::to_command(&argv)?.status()?;
+    } else {
+        launch::spawn(&mut launch::in_terminal(&argv)?)?;
+    }
+    Ok(())
+}
+
+/// Main categories from the menu spec, offered when creating an entry.
+const CATEGORIES: [&str; 11] = [
+    "AudioVideo",
+    "Development",
+    "Education",
+    "Game",
+    "Graphics",
+    "Network",
+    "Office",
+    "Science",
+    "Settings",
+    "System",
+    "Utility",
+];
+
+/// Asks for a name, whether it needs a terminal and a category through
+/// the menu, and writes a desktop file running `binary` into
+/// `LOCAL_APPLICATIONS`. Returns `None` if any question was cancelled.
+pub fn create(binary: &str, menu: &dyn crate::menu::Menu) -> Result<Option<PathBuf>> {
+    let file_name = format!(
+        "{}.desktop",
+        std::path::Path::new(binary)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+    );
+    let path = LOCAL_APPLICATIONS.join(file_name);
+    if path.exists() {
+        return Err(crate::tr!("{} already exists", path.display()).into());
+    }
+    let name = menu.choose(&crate::tr!("Name"), &[binary.to_string()])?;
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let terminal = menu.confirm(&crate::tr!("Run in a terminal?"))?;
+    let categories = CATEGORIES.map(ToString::to_string);
+    let category = menu.choose(&crate::tr!("Category"), &categories)?;
+    if category.is_empty() {
+        return Ok(None);
+    }
+    // Spaces and the reserved characters need quoting in Exec
+    let exec = if binary.chars().any(|c| " \t\"'\\><~|&;$*?#()`".contains(c)) {
+        let escaped = binary
+            .chars()
+            .flat_map(|c| match c {
+                '"' | '`' | '$' | '\\' => vec!['\\', c],
+                c => vec![c],
+            })
+            .collect::<String>();
+        format!("\"{}\"", escaped)
+    } else {
+        binary.to_string()
+    };
+    std::fs::create_dir_all(&*LOCAL_APPLICATIONS)?;
+    std::fs::write(
+        &path,
+        format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec={}\nTerminal={}\nCategories={};\n",
+            name.replace('\n', " "),
+            exec.replace('%', "%%"),
+            terminal,
+            category.trim_end_matches(';'),
+        ),
+    )?;
+    Ok(Some(path))
+}
+
+/// The changes `tweak` can make.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tweak {
+    Wayland,
+    Terminal,
+    NoDisplay,
+    Gpu,
+    Argument,
+    Environment,
+}
+
+/// Asks for a common change through the menu and makes it to (a local copy
+/// of) a desktop file: running Electron and Chromium apps natively on
+/// Wayland, toggling `Terminal`, hiding it, preferring the discrete GPU,
+/// adding an argument or setting an environment variable. Returns the path
+/// of the copy, or `None` if a question was cancelled.
+pub fn tweak(file_name: &str, menu: &dyn crate::menu::Menu) -> Result<Option<PathBuf>> {
+    let entry =
+        DesktopEntry::find(file_name).ok_or_else(|| crate::tr!("Desktop file not found"))?;
+    let terminal = if entry.get_bool("Terminal") {
+        crate::tr!("Don't run in a terminal")
+    } else {
+        crate::tr!("Run in a terminal")
+    };
+    let tweaks = [
+        (crate::tr!("Run natively on Wayland"), Tweak::Wayland),
+        (terminal, Tweak::Terminal),
+        (crate::tr!("Hide from menus"), Tweak::NoDisplay),
+        (crate::tr!("Prefer the discrete GPU"), Tweak::Gpu),
+        (crate::tr!("Add an argument"), Tweak::Argument),
+        (
+            crate::tr!("Set an environment variable"),
+            Tweak::Environment,
+        ),
+    ];
+    let lines = tweaks.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+    let output = menu.choose(&crate::tr!("Tweak {}:", file_name), &lines)?;
+    let Some((_, tweak)) = tweaks.iter().find(|x| x.0 == output) else {
+        return Ok(None);
+    };
+    let path = make_local(file_name)?;
+    let contents = std::fs::read_to_string(&path)?;
+    let exec = raw_value(&contents, "Exec").unwrap_or_default();
+    let contents = match tweak {
+        Tweak::Wayland => set_value(
+            &contents,
+            "Exec",
+            &add_argument(&exec, "--ozone-platform=wayland"),
+        ),
+        Tweak::Terminal => set_value(
+            &contents,
+            "Terminal",
+            &(!entry.get_bool("Terminal")).to_string(),
+        ),
+        Tweak::NoDisplay => set_value(&contents, "NoDisplay", "true"),
+        Tweak::Gpu => set_value(&contents, "PrefersNonDefaultGPU", "true"),
+        Tweak::Argument => {
+            let argument = menu.choose(&crate::tr!("Argument"), &[])?;
+            if argument.is_empty() {
+                return Ok(None);
+            }
+            set_value(
+                &contents,
+                "Exec",
+                &add_argument(&exec, &argument.replace('%', "%%")),
+            )
+        }
+        Tweak::Environment => {
+            let variable = menu.choose(&crate::tr!("Variable (NAME=value)"), &[])?;
+            if !variable.contains('=') || variable.starts_with('=') {
+                return Ok(None);
+            }
+            let variable = variable.replace('%', "%%");
+            let exec = match exec.strip_prefix("env ") {
+                Some(rest) => format!("env {} {}", variable, rest),
+                None => format!("env {} {}", variable, exec),
+            };
+            set_value(&contents, "Exec", &exec)
+        }
+    };
+    // Replaced rather than written in place, so the directory changes too
+    let temp = path.with_extension("desktop.tmp");
+    std::fs::write(&temp, contents)?;
+    std::fs::rename(&temp, &path)?;
+    Ok(Some(path))
+}
+
+/// `key` in the `[Desktop Entry]` group of `contents`, as written.
+fn raw_value(contents: &str, key: &str) -> Option<String> {
+    let mut in_entry = false;
+    for line in contents.lines() {
+        if line.starts_with('[') {
+            in_entry = line.trim() == "[Desktop Entry]";
+        } else if let Some((k, v)) = line.split_once('=').filter(|_| in_entry) {
+            if k.trim() == key {
+                return Some(v.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Sets `key` in the `[Desktop Entry]` group of `contents`, adding it at
+/// the end of the group if it isn't there yet.
+fn set_value(contents: &str, key: &str, value: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_entry = false;
+    let mut done = false;
+    for line in contents.lines() {
+        if line.starts_with('[') {
+            if in_entry && !done {
+                lines.push(format!("{}={}", key, value));
+                done = true;
+            }
+            in_entry = line.trim() == "[Desktop Entry]";
+        } else if in_entry && line.split_once('=').is_some_and(|x| x.0.trim() == key) {
+            lines.push(format!("{}={}", key, value));
+            done = true;
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    if in_entry && !done {
+        lines.push(format!("{}={}", key, value));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Adds `argument` to an `Exec` value, before its field codes like `%U`.
+fn add_argument(exec: &str, argument: &str) -> String {
+    let mut words = exec.split(' ').collect::<Vec<_>>();
+    if words.contains(&argument) {
+        return exec.to_string();
+    }
+    let at = words
+        .iter()
+        .position(|x| x.len() == 2 && x.starts_with('%'))
+        .unwrap_or(words.len());
+    words.insert(at, argument);
+    words.join(" ")
+}
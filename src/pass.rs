@@ -0,0 +1,82 @@
+//! Entries of pass(1), the standard unix password manager, for
+//! `dmenu_drun pass`.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::CONFIG;
+
+/// `$PASSWORD_STORE_DIR`, or else `~/.password-store`.
+fn store() -> PathBuf {
+    std::env::var_os("PASSWORD_STORE_DIR")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dirs::home_dir().unwrap_or_default().join(".password-store"))
+}
+
+/// The names of the entries in the store, like `email/work`, sorted.
+pub fn entries() -> Vec<String> {
+    let store = store();
+    let mut entries = Vec::new();
+    find(&store, &store, &mut entries);
+    entries.sort();
+    entries
+}
+
+fn find(store: &Path, dir: &Path, entries: &mut Vec<String>) {
+    let Ok(files) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in files.flatten().map(|x| x.path()) {
+        // Skips .git and .gpg-id
+        if path
+            .file_name()
+            .is_some_and(|x| x.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            find(store, &path, entries);
+        } else if path.extension().is_some_and(|x| x == "gpg") {
+            if let Ok(entry) = path.with_extension("").strip_prefix(store) {
+                entries.push(entry.to_string_lossy().to_string());
+            }
+        }
+    }
+}
+
+/// What to do with the password of `entry`, `action` in the `[pass]`
+/// section: `copy` (the default) copies it with `pass show -c`, `type`
+/// types it with `typer` (wtype on Wayland, xdotool on X11 by default).
+/// The password is never part of a command line.
+pub fn command(entry: &str) -> Command {
+    let action = CONFIG.get("pass", "action");
+    if action != Some("type") {
+        // Typing into whatever has focus is no fallback for a typo
+        if let Some(x) = action.filter(|x| *x != "copy") {
+            eprintln!("dmenu_drun: unknown pass action `{}`, copying", x);
+        }
+        let mut command = Command::new("pass");
+        command.args(["show", "-c", "--", entry]);
+        return command;
+    }
+    let wayland = crate::menu::is_wayland();
+    let typer = match CONFIG.get("pass", "typer") {
+        Some(x) => x,
+        None if wayland => "wtype",
+        None => "xdotool",
+    };
+    let typer = match typer {
+        "wtype" => "wtype -",
+        "xdotool" => "xdotool type --clearmodifiers --file -",
+        // A custom command, reading the password from stdin
+        x => x,
+    };
+    let mut command = Command::new("sh");
+    command.args([
+        "-c",
+        &format!("pass show -- \"$1\" | head -n 1 | tr -d '\\n' | {}", typer),
+        "sh",
+        entry,
+    ]);
+    command
+}
@@ -0,0 +1,173 @@
+//! Directories to jump to, opened in the file manager or a terminal.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use itertools::Itertools;
+
+use crate::config::CONFIG;
+use crate::{launch, Result};
+
+lazy_static::lazy_static! {
+    /// Used when zoxide isn't installed, one directory per line, newest last.
+    pub static ref RECENT_DIRS_PATH: PathBuf = dirs::data_local_dir()
+        .unwrap_or_default()
+        .join("dmenu_drun")
+        .join("recent_dirs");
+}
+
+/// Directories from zoxide's database, most frecent first. Falls back to
+/// `recent_dirs`, newest first.
+pub fn recent() -> Vec<PathBuf> {
+    let zoxide = Command::new("zoxide")
+        .args(["query", "--list"])
+        .output()
+        .ok()
+        .filter(|x| x.status.success());
+    let dirs = match zoxide {
+        Some(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(PathBuf::from)
+            .collect_vec(),
+        None => std::fs::read_to_string(&*RECENT_DIRS_PATH)
+            .unwrap_or_default()
+            .lines()
+            .rev()
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| PathBuf::from(crate::expand_tilde(x.trim())))
+            .collect_vec(),
+    };
+    dirs.into_iter().unique().filter(|x| x.is_dir()).collect()
+}
+
+/// Shows `dir` with the home directory as `~`.
+pub fn display(dir: &Path) -> String {
+    match dirs::home_dir().and_then(|home| dir.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => dir.display().to_string(),
+    }
+}
+
+/// Opens `dir` as configured by `open` in the `[places]` section: `files`
+/// (the default) uses xdg-open, `terminal` starts a shell there.
+pub fn open_command(dir: &Path) -> Result<Command> {
+    match CONFIG.get("places", "open") {
+        Some("terminal") => terminal_command(dir),
+        Some(x) if x != "files" => {
+            eprintln!("dmenu_drun: unknown places opener `{}`, using xdg-open", x);
+            Ok(xdg_open(dir))
+        }
+        _ => Ok(xdg_open(dir)),
+    }
+}
+
+/// Opens `path` with its default application.
+pub fn xdg_open(path: &Path) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(path);
+    command
+}
+
+/// How terminals that don't inherit the working directory (e.g. because a
+/// server process opens the window) are told where to start. `{}` is
+/// replaced with the directory.
+const WORKING_DIRECTORY_FLAGS: [(&str, &str); 11] = [
+    ("alacritty", "--working-directory {}"),
+    ("foot", "--working-directory={}"),
+    ("footclient", "--working-directory={}"),
+    ("gnome-terminal", "--working-directory={}"),
+    ("kitty", "--directory {}"),
+    ("konsole", "--workdir {}"),
+    ("st", "-d {}"),
+    ("terminator", "--working-directory={}"),
+    ("tilix", "--working-directory={}"),
+    ("urxvt", "-cd {}"),
+    ("xfce4-terminal", "--working-directory={}"),
+];
+
+/// A shell in `dir`, in the configured terminal. `terminal_cwd` in the
+/// `[places]` section overrides the flags passed for the directory.
+pub fn terminal_command(dir: &Path) -> Result<Command> {
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+    let mut argv = launch::terminal();
+    let program = argv
+        .first()
+        .ok_or_else(|| crate::tr!("No terminal is configured"))?;
+    let program = Path::new(program)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let flags = CONFIG.get("places", "terminal_cwd").or_else(|| {
+        WORKING_DIRECTORY_FLAGS
+            .iter()
+            .find(|x| x.0 == program)
+            .map(|x| x.1)
+    });
+    if let Some(flags) = flags {
+        let dir = dir.to_string_lossy();
+        let flags = flags.split_whitespace().map(|x| x.replace("{}", &dir));
+        argv.splice(1..1, flags);
+    }
+    argv.push(shell);
+    let mut command = launch::to_command(&argv)?;
+    command.current_dir(dir);
+    Ok(command)
+}
+
+/// Bookmarked directories, from GTK's bookmarks file.
+pub fn bookmarks() -> Vec<PathBuf> {
+    let Some(config_dir) = dirs::config_dir() else {
+        return Vec::new();
+    };
+    std::fs::read_to_string(config_dir.join("gtk-3.0/bookmarks"))
+        .unwrap_or_default()
+        .lines()
+        // `file:///home/me/src Label`, remote locations are skipped
+        .filter_map(|x| x.split(' ').next()?.strip_prefix("file://"))
+        .map(|x| PathBuf::from(percent_decode(x)))
+        .filter(|x| x.is_dir())
+        .collect()
+}
+
+/// The XDG user directories, like `~/Documents`, that exist.
+pub fn user_dirs() -> Vec<PathBuf> {
+    [
+        dirs::home_dir(),
+        dirs::desktop_dir(),
+        dirs::document_dir(),
+        dirs::download_dir(),
+        dirs::audio_dir(),
+        dirs::picture_dir(),
+        dirs::video_dir(),
+        dirs::public_dir(),
+        dirs::template_dir(),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|x| x.is_dir())
+    .collect()
+}
+
+/// Decodes `%20` and the like in a URI path.
+pub fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| std::str::from_utf8(tail.get(..2)?).ok())
+            .flatten()
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match decoded {
+            Some(x) => {
+                bytes.push(x);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
@@ -0,0 +1,112 @@
+//! Applications on other machines, launched over SSH with X11 forwarding or
+//! waypipe. Hosts are listed with `hosts` in the `[remote]` section.
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use itertools::Itertools;
+
+use crate::config::CONFIG;
+use crate::desktop::DesktopEntry;
+use crate::Result;
+
+/// Prints `Name` and `Exec` of the desktop files on the remote side,
+/// separated by a tab. Only the usual directories are scanned, to not
+/// depend on anything being installed there.
+const SCAN: &str = r#"for f in ~/.local/share/applications/*.desktop /usr/local/share/applications/*.desktop /usr/share/applications/*.desktop; do
+    [ -f "$f" ] || continue
+    grep -q '^NoDisplay=true' "$f" && continue
+    name=$(grep -m1 '^Name=' "$f" | cut -d= -f2-)
+    exec=$(grep -m1 '^Exec=' "$f" | cut -d= -f2-)
+    [ -n "$name" ] && [ -n "$exec" ] && printf '%s\t%s\n' "$name" "$exec"
+done"#;
+
+pub fn hosts() -> Vec<String> {
+    CONFIG
+        .get("remote", "hosts")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn cache_path(host: &str) -> PathBuf {
+    crate::config::namespaced(
+        dirs::cache_dir()
+            .unwrap_or_default()
+            .join(format!(".dmenu_rs_cache.remote.{}", host)),
+    )
+}
+
+/// The applications on `host`, as (name, Exec line). They're scanned again
+/// when the cached list is older than `refresh` in the `[remote]` section
+/// (default `1d`).
+pub fn apps(host: &str) -> Result<Vec<(String, String)>> {
+    let path = cache_path(host);
+    let refresh = CONFIG
+        .get("remote", "refresh")
+        .and_then(crate::history::parse_duration)
+        .unwrap_or(crate::history::DAY);
+    let fresh = path
+        .metadata()
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.elapsed().ok())
+        .is_some_and(|x| x.as_secs() < refresh);
+    let list = if fresh {
+        std::fs::read_to_string(&path)?
+    } else {
+        let output = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", host, SCAN])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| crate::tr!("Could not run ssh: {}", e))?;
+        if !output.status.success() {
+            return Err(crate::tr!(
+                "Could not list the applications on {}: {}",
+                host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let list = String::from_utf8_lossy(&output.stdout).to_string();
+        std::fs::write(&path, &list)?;
+        list
+    };
+    Ok(list
+        .lines()
+        .filter_map(|x| x.split_once('\t'))
+        .map(|(name, exec)| (name.to_string(), exec.to_string()))
+        .unique_by(|x| x.0.clone())
+        .sorted()
+        .collect())
+}
+
+/// Runs `exec` (an `Exec` line) on `host`, showing its windows here.
+/// `forward` in the `[remote]` section is `waypipe` or `x11` (`ssh -X`),
+/// defaulting to waypipe on Wayland.
+pub fn command(host: &str, exec: &str) -> Option<Command> {
+    let mut entry = DesktopEntry::default();
+    entry.keys.insert("Exec".to_string(), exec.to_string());
+    // ssh passes the command to the remote shell as one line
+    let line = crate::launch::shell_join(&entry.exec_argv()?);
+    let waypipe = match CONFIG.get("remote", "forward") {
+        Some("waypipe") => true,
+        Some("x11") => false,
+        Some(x) => {
+            eprintln!("dmenu_drun: unknown forwarding `{}`, using x11", x);
+            false
+        }
+        None => crate::menu::is_wayland(),
+    };
+    let mut command = if waypipe {
+        let mut command = Command::new("waypipe");
+        command.arg("ssh");
+        command
+    } else {
+        let mut command = Command::new("ssh");
+        command.arg("-X");
+        command
+    };
+    command.args([host, &line]);
+    Some(command)
+}
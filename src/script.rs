@@ -0,0 +1,115 @@
+//! User scripts in `~/.config/dmenu_drun/scripts/*.rhai`, only available
+//! with the `scripting` feature. A script can define any of:
+//! ```rhai
+//! // Extra entries, ran with `sh -c`
+//! fn entries() { [#{ name: "Project X", exec: "code ~/src/x" }] }
+//! // Changes how a line is shown in the menu
+//! fn display(name) { name.to_upper() }
+//! // Return false to refuse launching an entry
+//! fn allow(name, value) { value != "poweroff" }
+//! ```
+#[cfg(feature = "scripting")]
+use rhai::{Engine, Scope, AST};
+
+#[cfg(feature = "scripting")]
+lazy_static::lazy_static! {
+    pub static ref SCRIPTS_DIR: std::path::PathBuf = crate::config::CONFIG_PATH
+        .with_file_name("scripts");
+}
+
+#[derive(Default)]
+pub struct Scripts {
+    #[cfg(feature = "scripting")]
+    engine: Engine,
+    #[cfg(feature = "scripting")]
+    scripts: Vec<AST>,
+}
+
+#[cfg(feature = "scripting")]
+impl Scripts {
+    pub fn load() -> Self {
+        let engine = Engine::new();
+        let mut paths = std::fs::read_dir(&*SCRIPTS_DIR)
+            .map(|x| x.filter_map(|x| x.ok()).map(|x| x.path()).collect())
+            .unwrap_or_else(|_| Vec::new());
+        paths.retain(|x| x.extension().is_some_and(|x| x == "rhai"));
+        paths.sort();
+        let scripts = paths
+            .into_iter()
+            .filter_map(|x| match engine.compile_file(x.clone()) {
+                Ok(ast) => Some(ast),
+                Err(e) => {
+                    eprintln!("dmenu_drun: {}: {}", x.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Self { engine, scripts }
+    }
+
+    /// Calls `name` in every script that defines it.
+    fn call<T: Clone + 'static>(&self, name: &str, args: impl rhai::FuncArgs + Clone) -> Vec<T> {
+        self.scripts
+            .iter()
+            .filter(|x| x.iter_functions().any(|x| x.name == name))
+            .filter_map(|ast| {
+                self.engine
+                    .call_fn(&mut Scope::new(), ast, name, args.clone())
+                    .map_err(|e| eprintln!("dmenu_drun: {}(): {}", name, e))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Entries contributed by scripts, as (name, command line).
+    pub fn entries(&self) -> Vec<(String, String)> {
+        self.call::<rhai::Array>("entries", ())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| {
+                let map = x.try_cast::<rhai::Map>()?;
+                let name = map.get("name")?.clone().into_string().ok()?;
+                let exec = map.get("exec")?.clone().into_string().ok()?;
+                Some((name, exec))
+            })
+            .collect()
+    }
+
+    /// Passes a menu line through every script's `display`.
+    pub fn display(&self, line: &str) -> String {
+        self.scripts
+            .iter()
+            .filter(|x| x.iter_functions().any(|x| x.name == "display"))
+            .fold(line.to_string(), |line, ast| {
+                self.engine
+                    .call_fn(&mut Scope::new(), ast, "display", (line.clone(),))
+                    .unwrap_or(line)
+            })
+    }
+
+    /// Whether every script's `allow` agrees to launch the entry.
+    pub fn allow(&self, name: &str, value: &str) -> bool {
+        self.call::<bool>("allow", (name.to_string(), value.to_string()))
+            .into_iter()
+            .all(|x| x)
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+impl Scripts {
+    pub fn load() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    pub fn display(&self, line: &str) -> String {
+        line.to_string()
+    }
+
+    pub fn allow(&self, _name: &str, _value: &str) -> bool {
+        true
+    }
+}
@@ -0,0 +1,225 @@
+//! Results from GNOME Shell search providers (contacts, files, characters,
+//! ...), queried over D-Bus with gdbus.
+//! See also: https://developer.gnome.org/documentation/tutorials/search-provider.html
+use std::collections::{BTreeMap, HashMap};
+use std::process::{Command, Stdio};
+
+use crate::config::{data_dirs, Config, CONFIG};
+
+const GROUP: &str = "Shell Search Provider";
+const INTERFACE: &str = "org.gnome.Shell.SearchProvider2";
+
+/// An installed provider, from `gnome-shell/search-providers/*.ini`.
+#[derive(Clone, Debug)]
+pub struct Provider {
+    /// The application's desktop file name
+    pub desktop_id: String,
+    bus_name: String,
+    object_path: String,
+}
+
+/// A result of a provider, with the index of the provider in `providers()`.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub provider: usize,
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// The installed providers that implement version 2 of the interface and
+/// aren't disabled by default. `providers` in the `[search]` section limits
+/// them to a space separated list of desktop file names.
+pub fn providers() -> Vec<Provider> {
+    let wanted = CONFIG
+        .get("search", "providers")
+        .map(|x| x.split_whitespace().collect::<Vec<_>>());
+    let mut providers = Vec::<Provider>::new();
+    for dir in data_dirs() {
+        let Ok(files) = std::fs::read_dir(dir.join("gnome-shell").join("search-providers")) else {
+            continue;
+        };
+        for file in files.flatten().map(|x| x.path()) {
+            if file.extension().is_none_or(|x| x != "ini") {
+                continue;
+            }
+            let Ok(ini) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+            let ini = Config::parse(&ini);
+            let (Some(desktop_id), Some(bus_name), Some(object_path)) = (
+                ini.get(GROUP, "DesktopId"),
+                ini.get(GROUP, "BusName"),
+                ini.get(GROUP, "ObjectPath"),
+            ) else {
+                continue;
+            };
+            let enabled = match &wanted {
+                Some(wanted) => wanted.contains(&desktop_id),
+                None => ini.get(GROUP, "DefaultDisabled") != Some("true"),
+            };
+            // The first directory wins, like for desktop files
+            if ini.get(GROUP, "Version") != Some("2")
+                || !enabled
+                || providers.iter().any(|x| x.desktop_id == desktop_id)
+            {
+                continue;
+            }
+            providers.push(Provider {
+                desktop_id: desktop_id.to_string(),
+                bus_name: bus_name.to_string(),
+                object_path: object_path.to_string(),
+            });
+        }
+    }
+    providers.sort_by(|a, b| a.desktop_id.cmp(&b.desktop_id));
+    providers
+}
+
+/// The results of every provider for `query`, at most `max_results`
+/// (default 5) from the `[search]` section per provider. Providers that
+/// don't answer are skipped.
+pub fn search(providers: &[Provider], query: &str) -> Vec<SearchResult> {
+    let max = CONFIG
+        .get("search", "max_results")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(5);
+    let terms = variant_strings(&query.split_whitespace().collect::<Vec<_>>());
+    let mut results = Vec::new();
+    for (i, provider) in providers.iter().enumerate() {
+        let Some(ids) = provider.call("GetInitialResultSet", &[&terms]) else {
+            continue;
+        };
+        let mut ids = strings(&ids)
+            .into_iter()
+            .map(|(x, _)| x)
+            .collect::<Vec<_>>();
+        ids.truncate(max);
+        if ids.is_empty() {
+            continue;
+        }
+        let Some(metas) = provider.call("GetResultMetas", &[&variant_strings(&ids)]) else {
+            continue;
+        };
+        results.extend(metas_of(&metas).into_iter().map(|meta| SearchResult {
+            provider: i,
+            id: meta.0,
+            name: meta.1,
+            description: meta.2,
+        }));
+    }
+    results
+}
+
+impl Provider {
+    /// Calls `method` with GVariant text arguments, returning what gdbus
+    /// printed.
+    fn call(&self, method: &str, args: &[&str]) -> Option<String> {
+        let output = Command::new("gdbus")
+            .args([
+                "call",
+                "--session",
+                "--timeout=2",
+                &format!("--dest={}", self.bus_name),
+                &format!("--object-path={}", self.object_path),
+                &format!("--method={}.{}", INTERFACE, method),
+            ])
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// The command that activates result `id`, found with `query`.
+    pub fn activate(&self, id: &str, query: &str) -> Command {
+        let terms = variant_strings(&query.split_whitespace().collect::<Vec<_>>());
+        let mut command = Command::new("gdbus");
+        command.args([
+            "call",
+            "--session",
+            &format!("--dest={}", self.bus_name),
+            &format!("--object-path={}", self.object_path),
+            &format!("--method={}.ActivateResult", INTERFACE),
+            &variant_string(id),
+            &terms,
+            // The X server time, which we don't know
+            "0",
+        ]);
+        command
+    }
+}
+
+fn variant_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn variant_strings(list: &[impl AsRef<str>]) -> String {
+    let list = list
+        .iter()
+        .map(|x| variant_string(x.as_ref()))
+        .collect::<Vec<_>>();
+    format!("[{}]", list.join(", "))
+}
+
+/// The strings in GVariant text like `(['a', "b'c"],)`, each with whether
+/// it's a dictionary key and the dictionary it's in.
+fn strings(s: &str) -> Vec<(String, Option<(bool, usize)>)> {
+    let mut strings = Vec::new();
+    let mut chars = s.chars().peekable();
+    let mut dict = None;
+    let mut dicts = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                dict = Some(dicts);
+                dicts += 1;
+            }
+            '}' => dict = None,
+            '\'' | '"' => {
+                let mut string = String::new();
+                while let Some(x) = chars.next() {
+                    match x {
+                        '\\' => string.extend(chars.next()),
+                        x if x == c => break,
+                        x => string.push(x),
+                    }
+                }
+                while chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+                let is_key = chars.peek() == Some(&':');
+                strings.push((string, dict.map(|x| (is_key, x))));
+            }
+            _ => {}
+        }
+    }
+    strings
+}
+
+/// The id, name and description of each result in the output of
+/// `GetResultMetas`, a list of dictionaries like
+/// `{'id': <'x'>, 'name': <'Name'>, 'description': <'...'>}`.
+fn metas_of(s: &str) -> Vec<(String, String, Option<String>)> {
+    let strings = strings(s);
+    let mut dicts = BTreeMap::<usize, HashMap<&str, &str>>::new();
+    for (i, (key, dict)) in strings.iter().enumerate() {
+        if let (Some((true, dict)), Some((value, _))) = (dict, strings.get(i + 1)) {
+            dicts.entry(*dict).or_default().insert(key, value);
+        }
+    }
+    dicts
+        .into_values()
+        .filter_map(|x| {
+            Some((
+                x.get("id")?.to_string(),
+                x.get("name")?.to_string(),
+                x.get("description").map(ToString::to_string),
+            ))
+        })
+        .collect()
+}
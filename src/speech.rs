@@ -0,0 +1,19 @@
+use std::process::{Command, Stdio};
+
+use crate::config::CONFIG;
+
+/// Speaks `text` through speech-dispatcher if `speak` is enabled in the
+/// `[general]` section, for users of screen readers. spd-say only queues
+/// the message, so this doesn't wait for it to be spoken.
+pub fn say(text: &str) {
+    if CONFIG.get("general", "speak") != Some("true") {
+        return;
+    }
+    let spoken = Command::new("spd-say")
+        .args(["--application-name", "dmenu_drun", "--", text])
+        .stdin(Stdio::null())
+        .status();
+    if let Err(e) = spoken {
+        eprintln!("dmenu_drun: could not run spd-say: {}", e);
+    }
+}
@@ -0,0 +1,104 @@
+//! A summary of the launch history for `dmenu_drun stats`.
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::history::{self, History, DAY};
+
+/// How many of the most used entries are shown.
+const TOP: usize = 10;
+
+/// Prints the most used entries, launches per day and week, and the desktop
+/// files that were never launched. `entries` maps names to entries, as in
+/// the cache.
+pub fn print(entries: &HashMap<String, String>, history: &History, json: bool) {
+    let names: HashMap<&String, &String> = entries.iter().map(|(k, v)| (v, k)).collect();
+    let name = |entry: &String| names.get(entry).map_or(entry.clone(), |x| x.to_string());
+    let most_used = history
+        .launches
+        .iter()
+        .sorted_by_key(|x| (std::cmp::Reverse(*x.1), x.0))
+        .take(TOP)
+        .collect_vec();
+    let today = history::now() / DAY;
+    let since = |days: u64| {
+        history
+            .days
+            .range(today.saturating_sub(days - 1)..)
+            .map(|x| x.1)
+            .sum::<u64>()
+    };
+    let never_used = entries
+        .iter()
+        .filter(|(_, v)| v.ends_with(".desktop") && !history.used.contains_key(*v))
+        .map(|x| x.0)
+        .sorted()
+        .collect_vec();
+
+    if json {
+        let most_used = most_used.iter().map(|(entry, launches)| {
+            format!(
+                r#"{{"entry":{},"name":{},"launches":{},"last_used":{}}}"#,
+                json_string(entry),
+                json_string(&name(entry)),
+                launches,
+                history.used.get(*entry).copied().unwrap_or_default()
+            )
+        });
+        let days = history
+            .days
+            .iter()
+            .map(|(day, launches)| format!(r#"{{"day":{},"launches":{}}}"#, day * DAY, launches));
+        println!(
+            r#"{{"most_used":[{}],"launches":{{"today":{},"week":{},"month":{}}},"days":[{}],"never_used":[{}]}}"#,
+            most_used.format(","),
+            since(1),
+            since(7),
+            since(30),
+            days.format(","),
+            never_used.iter().map(|x| json_string(x)).format(",")
+        );
+        return;
+    }
+
+    println!("{}", crate::tr!("Most used:"));
+    for (entry, launches) in most_used {
+        let last_used = history
+            .used
+            .get(entry)
+            .map(|x| history::ago(*x))
+            .unwrap_or_default();
+        println!("{:>8}  {} ({})", launches, name(entry), last_used);
+    }
+    println!(
+        "{}",
+        crate::tr!(
+            "Launches: {} today, {} in the last 7 days, {} in the last 30 days",
+            since(1),
+            since(7),
+            since(30)
+        )
+    );
+    println!("{}", crate::tr!("Never used ({}):", never_used.len()));
+    for name in never_used {
+        println!("          {}", name);
+    }
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,277 @@
+//! Tests for the launch flow, with a menu answering from a script and a
+//! spawner recording what would have been started.
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Once;
+
+use super::*;
+
+/// The config every test runs with, the first test to start writes it.
+const CONFIG_FILE: &str = "\
+[general]
+elevate = sudo
+raw_commands = allowlist
+raw_allowlist = echo vim
+
+[confirm]
+htop = true
+";
+
+/// Points the config at a scratch directory, before anything reads it.
+/// Returns the directory, for the history.
+fn setup() -> PathBuf {
+    static SETUP: Once = Once::new();
+    let dir = std::env::temp_dir().join(format!("dmenu_drun-test-{}", std::process::id()));
+    SETUP.call_once(|| {
+        std::fs::create_dir_all(dir.join("dmenu_drun")).unwrap();
+        std::fs::write(dir.join("dmenu_drun").join("config"), CONFIG_FILE).unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+    });
+    dir
+}
+
+/// Answers every prompt with the next of `answers`, then with nothing.
+struct FakeMenu {
+    answers: RefCell<VecDeque<String>>,
+    prompts: RefCell<Vec<String>>,
+}
+
+impl FakeMenu {
+    fn new(answers: &[&str]) -> Self {
+        Self {
+            answers: RefCell::new(answers.iter().map(ToString::to_string).collect()),
+            prompts: RefCell::default(),
+        }
+    }
+}
+
+impl menu::Menu for FakeMenu {
+    fn show(
+        &self,
+        _histfile: Option<&Path>,
+        prompt: Option<&str>,
+        _lines: &[String],
+    ) -> Result<String> {
+        self.prompts
+            .borrow_mut()
+            .push(prompt.unwrap_or_default().to_string());
+        Ok(self.answers.borrow_mut().pop_front().unwrap_or_default())
+    }
+}
+
+/// Keeps the argument vector of every command instead of starting it,
+/// claiming to be a dry run if `dry_run` is set.
+#[derive(Default)]
+struct Recorder {
+    launched: Vec<Vec<String>>,
+    dry_run: bool,
+}
+
+impl launch::Spawner for Recorder {
+    fn spawn(&mut self, command: &mut Command) -> Result {
+        let argv = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|x| x.to_string_lossy().to_string())
+            .collect();
+        self.launched.push(argv);
+        Ok(())
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
+
+/// A cache with the executables `vim`, `htop` and `rm`, and a script's
+/// command.
+fn cache() -> Cache {
+    let mut cache = Cache::default();
+    for x in ["vim", "htop", "rm"] {
+        cache.entries.insert(x.to_string(), x.to_string());
+    }
+    cache
+        .commands
+        .insert("Greet".to_string(), "echo hello".to_string());
+    cache
+}
+
+/// Launches `output` as if it was picked from the menu, returning what
+/// was started.
+fn launch(output: &str, menu: &FakeMenu) -> (Result, Vec<Vec<String>>) {
+    let history_file = setup().join("history");
+    let mut history = history::History::load(Some(&history_file));
+    let mut spawner = Recorder::default();
+    let result = launch_selection(
+        &cache(),
+        &mut history,
+        &script::Scripts::default(),
+        menu,
+        &mut spawner,
+        &Flags::default(),
+        "drun",
+        output,
+    );
+    (result, spawner.launched)
+}
+
+#[test]
+fn launches_entry() {
+    let (result, launched) = launch("vim", &FakeMenu::new(&[]));
+    assert!(result.is_ok());
+    assert_eq!(launched, [["vim"]]);
+}
+
+#[test]
+fn launches_command() {
+    let (result, launched) = launch("Greet", &FakeMenu::new(&[]));
+    assert!(result.is_ok());
+    assert_eq!(launched, [["sh", "-c", "echo hello"]]);
+}
+
+#[test]
+fn launches_entry_with_arguments() {
+    let (result, launched) = launch("vim ~/notes", &FakeMenu::new(&[]));
+    assert!(result.is_ok());
+    let notes = format!("{}/notes", dirs::home_dir().unwrap_or_default().display());
+    assert_eq!(launched, [["vim", &notes]]);
+}
+
+#[test]
+fn launches_raw_command() {
+    let (result, launched) = launch("echo 'hello world'", &FakeMenu::new(&[]));
+    assert!(result.is_ok());
+    assert_eq!(launched, [["echo", "hello world"]]);
+}
+
+#[test]
+fn refuses_raw_command_not_in_allowlist() {
+    // `rm` is in the menu, but with arguments it's typed
+    let (result, launched) = launch("rm -rf x", &FakeMenu::new(&[]));
+    assert!(result.is_err());
+    assert!(launched.is_empty());
+}
+
+#[test]
+fn elevates_with_root_prefix() {
+    let (result, launched) = launch("#vim", &FakeMenu::new(&[]));
+    assert!(result.is_ok());
+    let argv = &launched[0];
+    assert_eq!(argv[0], "sudo");
+    assert_eq!(argv[argv.len() - 2..], ["--", "vim"]);
+}
+
+#[test]
+fn refuses_bare_root_prefix() {
+    let (result, launched) = launch("#", &FakeMenu::new(&[]));
+    assert!(result.is_err());
+    assert!(launched.is_empty());
+}
+
+#[test]
+fn cancels_when_not_confirmed() {
+    let menu = FakeMenu::new(&["no"]);
+    let (result, launched) = launch("htop", &menu);
+    assert!(result.is_err_and(|e| e.is::<Cancelled>()));
+    assert!(launched.is_empty());
+    assert_eq!(menu.prompts.borrow().len(), 1);
+}
+
+#[test]
+fn dry_run_leaves_history_alone() {
+    let history_file = setup().join("dry-run-history");
+    let before = "used\tvim\t1650000000\t3\n";
+    std::fs::write(&history_file, before).unwrap();
+    let mut history = history::History::load(Some(&history_file));
+    let mut spawner = Recorder {
+        dry_run: true,
+        ..Recorder::default()
+    };
+    for output in ["vim", "vim ~/notes", "nothing-like-this"] {
+        let _ = launch_selection(
+            &cache(),
+            &mut history,
+            &script::Scripts::default(),
+            &FakeMenu::new(&[]),
+            &mut spawner,
+            &Flags::default(),
+            "drun",
+            output,
+        );
+    }
+    assert!(!spawner.launched.is_empty());
+    assert_eq!(std::fs::read_to_string(&history_file).unwrap(), before);
+}
+
+#[test]
+fn imports_escaped_environment() {
+    let text = "\
+LANG=en_US.UTF-8
+export EDITOR=\"vim -p\"
+# SSH_AUTH_SOCK=/nowhere
+MOTD=$'one\\ttwo\\nit\\'s \\\\ \\x41\\102 \\q'
+";
+    let vars = launch::parse_environment(text);
+    let vars = vars
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vars,
+        [
+            ("LANG", "en_US.UTF-8"),
+            ("EDITOR", "vim -p"),
+            ("MOTD", "one\ttwo\nit's \\ AB \\q"),
+        ]
+    );
+}
+
+#[test]
+fn parses_durations() {
+    use history::{parse_duration, DAY};
+    assert_eq!(parse_duration("90"), Some(90));
+    assert_eq!(parse_duration("90s"), Some(90));
+    assert_eq!(parse_duration("30m"), Some(30 * 60));
+    assert_eq!(parse_duration("12h"), Some(12 * 60 * 60));
+    assert_eq!(parse_duration("7d"), Some(7 * DAY));
+    assert_eq!(parse_duration("2w"), Some(14 * DAY));
+    assert_eq!(parse_duration("m"), None);
+    assert_eq!(parse_duration("5y"), None);
+    assert_eq!(parse_duration("99999999999999999w"), None);
+    assert_eq!(parse_duration("99999999999999999999"), None);
+}
+
+#[test]
+fn expands_field_codes() {
+    let file = "[Desktop Entry]\n\
+                Name=Viewer\n\
+                Icon=viewer\n\
+                Exec=viewer --title=%c 100%% %%c %%%k %i %f %d\n";
+    let entry = DesktopEntry::from_reader(Path::new("/apps/viewer.desktop"), file.as_bytes());
+    assert_eq!(
+        entry.exec_argv().unwrap(),
+        [
+            "viewer",
+            "--title=Viewer",
+            "100%",
+            "%c",
+            "%/apps/viewer.desktop",
+            "--icon",
+            "viewer",
+        ]
+    );
+    assert_eq!(
+        entry.open_argv("a.png").unwrap(),
+        [
+            "viewer",
+            "--title=Viewer",
+            "100%",
+            "%c",
+            "%/apps/viewer.desktop",
+            "--icon",
+            "viewer",
+            "a.png",
+        ]
+    );
+}
@@ -0,0 +1,287 @@
+//! A subset of the XDG menu spec: the `applications.menu` tree with its
+//! include/exclude rules, merged files and directory entries.
+//! See also: https://specifications.freedesktop.org/menu-spec/latest/
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::config::{config_dirs, data_dirs};
+use crate::desktop::DesktopEntry;
+
+/// A rule inside `<Include>` or `<Exclude>`.
+#[derive(Clone, Debug)]
+enum Rule {
+    Category(String),
+    Filename(String),
+    And(Vec<Rule>),
+    Or(Vec<Rule>),
+    Not(Vec<Rule>),
+    All,
+}
+
+impl Rule {
+    fn matches(&self, app: &App) -> bool {
+        match self {
+            Self::Category(x) => app.categories.contains(x),
+            Self::Filename(x) => &app.id == x,
+            Self::And(rules) => rules.iter().all(|x| x.matches(app)),
+            Self::Or(rules) => rules.iter().any(|x| x.matches(app)),
+            Self::Not(rules) => !rules.iter().any(|x| x.matches(app)),
+            Self::All => true,
+        }
+    }
+
+    fn parse_children(node: roxmltree::Node) -> Vec<Self> {
+        node.children()
+            .filter(|x| x.is_element())
+            .filter_map(Self::parse)
+            .collect()
+    }
+
+    fn parse(node: roxmltree::Node) -> Option<Self> {
+        let text = || node.text().unwrap_or_default().trim().to_string();
+        Some(match node.tag_name().name() {
+            "Category" => Self::Category(text()),
+            "Filename" => Self::Filename(text()),
+            "And" => Self::And(Self::parse_children(node)),
+            "Or" => Self::Or(Self::parse_children(node)),
+            "Not" => Self::Not(Self::parse_children(node)),
+            "All" => Self::All,
+            _ => return None,
+        })
+    }
+}
+
+/// A desktop entry, as far as the menu rules are concerned.
+#[derive(Clone, Debug)]
+pub struct App {
+    /// The desktop file name, e.g. `firefox.desktop`
+    pub id: String,
+    pub categories: Vec<String>,
+}
+
+impl App {
+    pub fn new(id: &str, entry: &DesktopEntry) -> Self {
+        Self {
+            id: id.to_string(),
+            categories: entry
+                .get("Categories")
+                .unwrap_or_default()
+                .split(';')
+                .filter(|x| !x.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// A `<Menu>`, before applying its rules.
+#[derive(Clone, Debug, Default)]
+struct MenuDef {
+    name: String,
+    directory: Option<String>,
+    /// (include?, rule), in document order
+    rules: Vec<(bool, Rule)>,
+    only_unallocated: bool,
+    deleted: bool,
+    submenus: Vec<MenuDef>,
+}
+
+impl MenuDef {
+    fn parse(node: roxmltree::Node, file: &Path, menu: &mut Self, depth: usize) {
+        for child in node.children().filter(|x| x.is_element()) {
+            let text = child.text().unwrap_or_default().trim();
+            match child.tag_name().name() {
+                "Name" => menu.name = text.to_string(),
+                "Directory" => menu.directory = Some(text.to_string()),
+                "OnlyUnallocated" => menu.only_unallocated = true,
+                "NotOnlyUnallocated" => menu.only_unallocated = false,
+                "Deleted" => menu.deleted = true,
+                "NotDeleted" => menu.deleted = false,
+                "Include" | "Exclude" => {
+                    let include = child.tag_name().name() == "Include";
+                    // Rules directly inside are or'ed together
+                    menu.rules.extend(
+                        Rule::parse_children(child)
+                            .into_iter()
+                            .map(|x| (include, x)),
+                    );
+                }
+                "Menu" => {
+                    let mut submenu = Self::default();
+                    Self::parse(child, file, &mut submenu, depth);
+                    menu.submenus.push(submenu);
+                }
+                "MergeFile" if child.attribute("type") != Some("parent") => {
+                    let path = file.parent().unwrap_or(Path::new("/")).join(text);
+                    Self::merge_file(&path, menu, depth);
+                }
+                "MergeDir" => {
+                    let dir = file.parent().unwrap_or(Path::new("/")).join(text);
+                    Self::merge_dir(&dir, menu, depth);
+                }
+                "DefaultMergeDirs" => {
+                    for dir in config_dirs() {
+                        Self::merge_dir(&dir.join("menus/applications-merged"), menu, depth);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Merges the contents of another menu file's root `<Menu>` into `menu`.
+    fn merge_file(path: &Path, menu: &mut Self, depth: usize) {
+        // Guard against merge loops
+        if depth > 8 {
+            return;
+        }
+        let Ok(xml) = std::fs::read_to_string(path) else {
+            return;
+        };
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let Ok(doc) = roxmltree::Document::parse_with_options(&xml, options) else {
+            eprintln!("dmenu_drun: could not parse {}", path.display());
+            return;
+        };
+        Self::parse(doc.root_element(), path, menu, depth + 1);
+    }
+
+    fn merge_dir(dir: &Path, menu: &mut Self, depth: usize) {
+        let mut files = std::fs::read_dir(dir)
+            .map(|x| x.filter_map(|x| x.ok()).map(|x| x.path()).collect())
+            .unwrap_or_else(|_| Vec::new());
+        files.retain(|x| x.extension().is_some_and(|x| x == "menu"));
+        files.sort();
+        for file in files {
+            Self::merge_file(&file, menu, depth);
+        }
+    }
+
+    /// Combines submenus with the same name, as merging can create those.
+    fn fold_duplicates(&mut self) {
+        let mut folded: Vec<Self> = Vec::new();
+        for submenu in std::mem::take(&mut self.submenus) {
+            match folded.iter_mut().find(|x| x.name == submenu.name) {
+                Some(x) => {
+                    x.directory = submenu.directory.or(x.directory.take());
+                    x.rules.extend(submenu.rules);
+                    x.only_unallocated |= submenu.only_unallocated;
+                    x.deleted = submenu.deleted;
+                    x.submenus.extend(submenu.submenus);
+                }
+                None => folded.push(submenu),
+            }
+        }
+        for submenu in &mut folded {
+            submenu.fold_duplicates();
+        }
+        self.submenus = folded;
+    }
+
+    fn matches(&self, app: &App) -> bool {
+        // Later rules override earlier ones
+        self.rules.iter().fold(false, |included, (include, rule)| {
+            if rule.matches(app) {
+                *include
+            } else {
+                included
+            }
+        })
+    }
+
+    /// Resolves the rules against `apps`. `allocated` collects everything
+    /// placed in a regular menu, for the `OnlyUnallocated` pass.
+    fn resolve(&self, apps: &[App], allocated: &mut HashSet<String>, unallocated: bool) -> Menu {
+        let entries = if self.only_unallocated == unallocated {
+            apps.iter()
+                .filter(|x| self.matches(x) && !(unallocated && allocated.contains(&x.id)))
+                .map(|x| x.id.clone())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if !unallocated {
+            allocated.extend(entries.iter().cloned());
+        }
+        Menu {
+            name: self.name.clone(),
+            directory: self.directory.clone(),
+            entries,
+            submenus: self
+                .submenus
+                .iter()
+                .filter(|x| !x.deleted)
+                .map(|x| x.resolve(apps, allocated, unallocated))
+                .collect(),
+        }
+    }
+}
+
+/// A menu with its rules applied.
+#[derive(Clone, Debug, Default)]
+pub struct Menu {
+    pub name: String,
+    directory: Option<String>,
+    /// Desktop file names
+    pub entries: Vec<String>,
+    pub submenus: Vec<Menu>,
+}
+
+impl Menu {
+    /// Loads `$XDG_MENU_PREFIX` `applications.menu` from the first
+    /// `$XDG_CONFIG_DIRS` that has it, and sorts `apps` into it.
+    pub fn load(apps: &[App]) -> Option<Self> {
+        let prefix = std::env::var("XDG_MENU_PREFIX").unwrap_or_default();
+        let file = config_dirs()
+            .into_iter()
+            .map(|x| x.join("menus").join(format!("{}applications.menu", prefix)))
+            .find(|x| x.is_file())?;
+        let mut root = MenuDef::default();
+        MenuDef::merge_file(&file, &mut root, 0);
+        root.fold_duplicates();
+        let mut allocated = HashSet::new();
+        let mut menu = root.resolve(apps, &mut allocated, false);
+        let unallocated = root.resolve(apps, &mut allocated, true);
+        menu.add(unallocated);
+        Some(menu)
+    }
+
+    fn add(&mut self, other: Menu) {
+        self.entries.extend(other.entries);
+        for (menu, other) in self.submenus.iter_mut().zip(other.submenus) {
+            menu.add(other);
+        }
+    }
+
+    /// The name to show, from the menu's `.directory` file if possible.
+    pub fn title(&self) -> String {
+        self.directory
+            .as_ref()
+            .and_then(|directory| {
+                data_dirs()
+                    .into_iter()
+                    .map(|x| x.join("desktop-directories").join(directory))
+                    .find(|x| x.is_file())
+            })
+            .and_then(|x| DesktopEntry::parse(&x).ok())
+            .and_then(|x| x.get_localized("Name").map(ToString::to_string))
+            .unwrap_or_else(|| self.name.clone())
+    }
+
+    /// Whether the menu or any of its submenus has entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.submenus.iter().all(Menu::is_empty)
+    }
+
+    /// All desktop file names in the menu and its submenus.
+    pub fn all_entries(&self) -> HashSet<String> {
+        let mut entries = self.entries.iter().cloned().collect::<HashSet<_>>();
+        for submenu in &self.submenus {
+            entries.extend(submenu.all_entries());
+        }
+        entries
+    }
+}